@@ -1,10 +1,59 @@
+use std::{env, fs, process};
+
 use nipah_tokenizer::tokenizer::tokenize;
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        Some("format") => process::exit(format_files(&args[1..])),
+        _ => hello_world()
+    }
+}
+
+fn hello_world() {
     let options = nipah_tokenizer::options::default();
-    
+
     let text = "Hello, World!".to_string();
 
     let tokens = tokenize(text, &options);
     println!("{:#?}", tokens);
 }
+
+/// `format [--check] <files...>`: formats files in place, or with `--check` only reports the ones that would change
+fn format_files(args: &[String]) -> i32 {
+    let check = args.iter().any(|arg| arg == "--check");
+    let files: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if files.is_empty() {
+        eprintln!("usage: console format [--check] <files...>");
+        return 2;
+    }
+
+    let options = nipah_tokenizer::options::default();
+    let print = nipah_tokenizer::format::default();
+
+    let mut status = 0;
+    for file in files {
+        let source = match fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("{}: {}", file, err);
+                status = 2;
+                continue;
+            }
+        };
+
+        let formatted = nipah_tokenizer::format::format(&source, &options, &print);
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{}", file);
+            status = status.max(1);
+        } else if let Err(err) = fs::write(file, formatted) {
+            eprintln!("{}: {}", file, err);
+            status = 2;
+        }
+    }
+    status
+}
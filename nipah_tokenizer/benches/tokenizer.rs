@@ -0,0 +1,184 @@
+//! Benchmarks of each stage of tokenizing over a few kinds of text.
+//!
+//! Run with `cargo bench -p nipah_tokenizer`, optionally followed by a filter such as `json` or `tokenize/`.
+//! Every benchmark reports MB/s of input, and the end-to-end runs are followed by their tokens per second and the
+//! memory each token takes in a `Vec<Token>` and in a `TokenBuffer`.
+
+use std::{borrow::Cow, time::{Duration, Instant}};
+
+use bencher::{black_box, run_tests_console, Bencher, TDynBenchFn, TestDesc, TestDescAndFn, TestFn, TestOpts};
+use nipah_tokenizer::{options::{self, TokenizerOptions}, token::Token, token_buffer::{TokenBuffer, tokens_heap_size}, tokenizer::{split_string, split_pieces_into, tokenize}};
+
+/// Rough size of every corpus, so their MB/s are comparable
+const CORPUS_SIZE: usize = 64 * 1024;
+
+fn repeat_to_size(piece: &str) -> String {
+    piece.repeat(CORPUS_SIZE / piece.len() + 1)
+}
+
+fn source_code() -> String {
+    repeat_to_size(concat!(
+        "fn fibonacci(n: int) -> int {\n",
+        "    // Iterative, to keep the stack small\n",
+        "    let a = 0; let b = 1;\n",
+        "    while n > 0 && a >= 0 {\n",
+        "        let next = a + b * 1.5 - 0x1F;\n",
+        "        a = b; b = next; n = n - 1;\n",
+        "    }\n",
+        "    print(\"result: \\\"\" + a + \"\\\"\\n\", 'x');\n",
+        "    return a != -1 ? a : b;\n",
+        "}\n\n"
+    ))
+}
+
+fn prose() -> String {
+    repeat_to_size(concat!(
+        "It was the best of times, it was the worst of times, it was the age of wisdom, it was the age of foolishness; ",
+        "it was the epoch of belief, it was the epoch of incredulity: it was the season of Light, it was the season of Darkness!\n",
+        "Çà et là, les élèves étudiaient — quietly — while the café’s owner counted 1,024 coins.\n"
+    ))
+}
+
+fn logs() -> String {
+    let mut logs = String::new();
+    let mut line = 0;
+    while logs.len() < CORPUS_SIZE {
+        logs.push_str(&format!(
+            "2024-03-{:02}T12:{:02}:{:02}.{:03}Z [{}] worker-{} 10.0.{}.{} GET /api/v1/items/{}?page={} -> {} in {}ms\n",
+            line % 28 + 1, line % 60, line * 7 % 60, line * 13 % 1000,
+            ["INFO", "WARN", "ERROR", "DEBUG"][line % 4], line % 8, line % 256, line * 3 % 256,
+            line * 31, line % 10, [200, 404, 500][line % 3], line % 250
+        ));
+        line += 1;
+    }
+    logs
+}
+
+fn json() -> String {
+    let mut json = String::from("[\n");
+    let mut id = 0;
+    while json.len() < CORPUS_SIZE {
+        json.push_str(&format!(
+            "  {{\"id\": {}, \"name\": \"item \\\"{}\\\"\", \"price\": {}.{:02}, \"tags\": [\"a\", \"b\"], \"active\": {}, \"parent\": null}},\n",
+            id, id, id * 3, id % 100, id % 2 == 0
+        ));
+        id += 1;
+    }
+    json.push_str("  {}\n]\n");
+    json
+}
+
+fn corpora() -> Vec<(&'static str, String)> {
+    vec![
+        ("source_code", source_code()),
+        ("prose", prose()),
+        ("logs", logs()),
+        ("json", json()),
+        // A run of separators the `==` aggregator keeps merging
+        ("equals", "=".repeat(8 * 1024)),
+        ("long_string", format!("\"{}\"", "ab\\n\\u{1F600}".repeat(CORPUS_SIZE / 12))),
+        ("nested", format!("{}{}", "(".repeat(CORPUS_SIZE / 2), ")".repeat(CORPUS_SIZE / 2)))
+    ]
+}
+
+/// What a benchmark measures over its corpus
+#[derive(Clone, Copy)]
+enum Stage {
+    /// `split_string` without any split aggregator
+    Split,
+    /// `split_string` with the default aggregators, the difference with `Split` being the aggregation
+    Aggregate,
+    /// `split_pieces_into`, splitting and aggregating into a reused buffer without copying any text
+    Pieces,
+    /// `Token::build` on every piece, cloning the pieces included
+    Build,
+    /// `tokenize` from start to end
+    Tokenize
+}
+impl Stage {
+    const ALL: [Stage; 5] = [Stage::Split, Stage::Aggregate, Stage::Pieces, Stage::Build, Stage::Tokenize];
+
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Split => "split_string",
+            Stage::Aggregate => "aggregate",
+            Stage::Pieces => "split_pieces",
+            Stage::Build => "build",
+            Stage::Tokenize => "tokenize"
+        }
+    }
+}
+
+struct Benchmark {
+    stage: Stage,
+    corpus: String
+}
+impl TDynBenchFn for Benchmark {
+    fn run(&self, b: &mut Bencher) {
+        let options = options::default();
+        let unaggregated = TokenizerOptions { split_aggregators: vec![], ..options::default() };
+        b.bytes = self.corpus.len() as u64;
+        match self.stage {
+            Stage::Split => b.iter(|| split_string(black_box(self.corpus.clone()), &unaggregated)),
+            Stage::Aggregate => b.iter(|| split_string(black_box(self.corpus.clone()), &options)),
+            Stage::Pieces => {
+                let mut pieces = vec![];
+                b.iter(|| {
+                    split_pieces_into(black_box(&self.corpus), &options, &mut pieces);
+                    pieces.len()
+                })
+            },
+            Stage::Build => {
+                let items = split_string(self.corpus.clone(), &options);
+                b.iter(|| items.iter().cloned().map(|item| Token::build(item, &options)).collect::<Vec<_>>())
+            },
+            Stage::Tokenize => b.iter(|| tokenize(black_box(self.corpus.clone()), &options))
+        }
+    }
+}
+
+/// Tokenizes `corpus` over and over for about a second, giving the tokens produced per second
+fn tokens_per_second(corpus: &str, options: &TokenizerOptions) -> f64 {
+    let start = Instant::now();
+    let mut tokens = 0;
+    while start.elapsed() < Duration::from_secs(1) {
+        tokens += black_box(tokenize(corpus.to_string(), options)).len();
+    }
+    tokens as f64 / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    let filter = std::env::args().skip(1).find(|arg| arg != "--bench");
+    let matches = |name: &str| filter.as_ref().map_or(true, |filter| name.contains(filter.as_str()));
+
+    let corpora = corpora();
+    let mut benches = vec![];
+    for stage in Stage::ALL {
+        for (name, corpus) in &corpora {
+            benches.push(TestDescAndFn {
+                desc: TestDesc { name: Cow::from(format!("{}/{}", stage.name(), name)), ignore: false },
+                testfn: TestFn::DynBenchFn(Box::new(Benchmark { stage, corpus: corpus.clone() }))
+            });
+        }
+    }
+    let opts = TestOpts { filter: filter.clone(), ..TestOpts::default() };
+    run_tests_console(&opts, benches).unwrap();
+
+    let options = options::default();
+    for (name, corpus) in &corpora {
+        let name = format!("tokenize/{}", name);
+        if matches(&name) {
+            println!("{:<24} {:>14.0} tokens/s", name, tokens_per_second(corpus, &options));
+        }
+    }
+    for (name, corpus) in &corpora {
+        let name = format!("memory/{}", name);
+        if matches(&name) {
+            let tokens = tokenize(corpus.clone(), &options);
+            let mut buffer = TokenBuffer::from(tokens.as_slice());
+            buffer.shrink_to_fit();
+            let in_vec = tokens_heap_size(&tokens) as f64 / tokens.len() as f64;
+            println!("{:<24} {:>8.1} bytes/token in Vec<Token> {:>8.1} in TokenBuffer", name, in_vec, buffer.bytes_per_token());
+        }
+    }
+}
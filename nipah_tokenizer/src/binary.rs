@@ -0,0 +1,525 @@
+use std::collections::HashMap;
+
+use crate::{token::{Token, TokenType, TokenData, TokenPosition, Span}, token_buffer::{TokenKind, plain_type}, error::{BinaryError, TokenizeErrorKind, DecodeErrorKind, IO_KINDS}, number::{NumberLiteral, ExactNumber, Radix}, options::LineEnding, interner::{Interner, Intern}};
+
+/// First bytes of every encoding
+pub const MAGIC: [u8; 4] = *b"NTOK";
+/// Version of the format `encode` writes, the only one `decode` reads
+pub const VERSION: u16 = 2;
+/// Magic, version and checksum
+const HEADER_LEN: usize = 10;
+
+const HAS_NUMBER: u8 = 1;
+const HAS_SCOPE: u8 = 2;
+
+/// Encodes tokens into bytes `decode` turns back into the same tokens, without lexing anything again.
+///
+/// The bytes are the `MAGIC`, the `VERSION` and a CRC-32 of the rest as little-endian `u16` and `u32`, followed by
+/// a table of every distinct string the tokens hold and then the tokens. Each token is its `TokenKind` byte, flags
+/// for the number and scope it may have, its span and position as offsets from the previous token, its raw text
+/// as an index into the table and its payload. Integers are LEB128 varints, zigzagged when signed.
+///
+/// IO errors outside of a few common kinds are kept as `io::ErrorKind::Other`. Fails on `Symbol` tokens, whose
+/// names only their interner knows, they are encoded by `encode_interned`.
+pub fn encode(tokens: &[Token]) -> Result<Vec<u8>, BinaryError> {
+    encode_with(tokens, None)
+}
+
+/// Encodes tokens as `encode`, writing the name of each `Symbol` token from `interner` to the string table
+pub fn encode_interned(tokens: &[Token], interner: &Interner) -> Result<Vec<u8>, BinaryError> {
+    encode_with(tokens, Some(interner))
+}
+
+fn encode_with(tokens: &[Token], interner: Option<&Interner>) -> Result<Vec<u8>, BinaryError> {
+    let mut encoder = Encoder { interner, ..Encoder::default() };
+    let mut previous = (0, 0);
+    for token in tokens {
+        encoder.token(token, previous)?;
+        previous = (token.1.span.end, token.1.position.line);
+    }
+
+    let mut out = Vec::with_capacity(HEADER_LEN + encoder.body.len() + encoder.table.iter().map(|text| text.len() + 2).sum::<usize>() + 10);
+    out.extend(MAGIC);
+    out.extend(VERSION.to_le_bytes());
+    out.extend([0; 4]);
+    write_varint(&mut out, encoder.table.len() as u64);
+    for text in &encoder.table {
+        write_varint(&mut out, text.len() as u64);
+        out.extend(text.as_bytes());
+    }
+    write_varint(&mut out, tokens.len() as u64);
+    out.extend(&encoder.body);
+
+    let checksum = crc32(&out[HEADER_LEN..]);
+    out[6..HEADER_LEN].copy_from_slice(&checksum.to_le_bytes());
+    Ok(out)
+}
+
+/// Decodes tokens written by `encode`, checking the header and the checksum before reading any of them.
+///
+/// `Symbol` tokens come back as `Id` tokens of their names.
+pub fn decode(bytes: &[u8]) -> Result<Vec<Token>, BinaryError> {
+    decode_with(bytes, None)
+}
+
+/// Decodes tokens as `decode`, interning the names of `Symbol` tokens into `interner` to give them their symbol there
+pub fn decode_interned(bytes: &[u8], mut interner: impl Intern) -> Result<Vec<Token>, BinaryError> {
+    decode_with(bytes, Some(&mut interner))
+}
+
+fn decode_with(bytes: &[u8], mut interner: Option<&mut dyn Intern>) -> Result<Vec<Token>, BinaryError> {
+    if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+        return Err(BinaryError::NotTokens);
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err(BinaryError::Truncated);
+    }
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != VERSION {
+        return Err(BinaryError::UnsupportedVersion(version));
+    }
+    let expected = u32::from_le_bytes(bytes[6..HEADER_LEN].try_into().unwrap());
+    let found = crc32(&bytes[HEADER_LEN..]);
+    if expected != found {
+        return Err(BinaryError::ChecksumMismatch { expected, found });
+    }
+
+    let mut reader = Reader { bytes, at: HEADER_LEN };
+    let strings = reader.len()?;
+    // Every string takes at least a byte, so a damaged count cannot reserve more than the input
+    let mut table = Vec::with_capacity(strings.min(reader.left()));
+    for _ in 0..strings {
+        let len = reader.len()?;
+        table.push(std::str::from_utf8(reader.take(len)?).map_err(|_| BinaryError::InvalidValue)?);
+    }
+
+    let count = reader.len()?;
+    let mut tokens = Vec::with_capacity(count.min(reader.left() / 7));
+    let mut previous = (0, 0);
+    for _ in 0..count {
+        let token = reader.token(&table, previous, interner.as_deref_mut())?;
+        previous = (token.1.span.end, token.1.position.line);
+        tokens.push(token);
+    }
+    if reader.left() > 0 {
+        return Err(BinaryError::TrailingBytes);
+    }
+    Ok(tokens)
+}
+
+#[derive(Default)]
+struct Encoder<'t> {
+    interner: Option<&'t Interner>,
+    strings: HashMap<&'t str, u32>,
+    table: Vec<&'t str>,
+    body: Vec<u8>
+}
+impl<'t> Encoder<'t> {
+    /// Writes the index of `text` in the string table, adding it the first time
+    fn string(&mut self, text: &'t str) {
+        let next = self.table.len() as u32;
+        let index = *self.strings.entry(text).or_insert(next);
+        if index == next {
+            self.table.push(text);
+        }
+        write_varint(&mut self.body, index as u64);
+    }
+
+    fn token(&mut self, Token(kind, data): &'t Token, (previous_end, previous_line): (usize, i32)) -> Result<(), BinaryError> {
+        let flags = if data.number.is_some() { HAS_NUMBER } else { 0 } | if data.scope.is_some() { HAS_SCOPE } else { 0 };
+        self.body.extend([TokenKind::of(kind) as u8, flags]);
+        write_signed(&mut self.body, data.span.start as i64 - previous_end as i64);
+        write_varint(&mut self.body, data.span.len() as u64);
+        write_signed(&mut self.body, data.position.line as i64 - previous_line as i64);
+        write_signed(&mut self.body, data.position.position as i64);
+        self.string(&data.text);
+
+        match kind {
+            TokenType::Any(value) | TokenType::StringLiteral(value) | TokenType::Id(value) => self.string(value),
+            TokenType::IntegerLiteral(value) => write_signed(&mut self.body, *value),
+            TokenType::FloatLiteral(value) => self.body.extend(value.to_bits().to_le_bytes()),
+            TokenType::CharLiteral(value) => write_varint(&mut self.body, *value as u64),
+            TokenType::ExactIntegerLiteral(value) | TokenType::ExactDecimalLiteral(value) => {
+                self.body.extend([value.is_negative() as u8, value.radix() as u8]);
+                self.string(value.digits());
+            },
+            TokenType::Symbol(value) => match self.interner {
+                Some(interner) if value.index() < interner.len() => self.string(interner.resolve(*value)),
+                _ => return Err(BinaryError::UnknownSymbol(*value))
+            },
+            TokenType::Newline(value) => self.body.push(line_ending_tag(*value)),
+            TokenType::Error(value) => self.body.extend(error_tags(value)),
+            _ => ()
+        }
+
+        if let Some(number) = &data.number {
+            self.body.push(number.radix as u8);
+            match &number.suffix {
+                Some(suffix) => {
+                    self.body.push(1);
+                    self.string(suffix);
+                },
+                None => self.body.push(0)
+            }
+        }
+        if let Some(scope) = data.scope {
+            write_signed(&mut self.body, scope);
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    at: usize
+}
+impl<'b> Reader<'b> {
+    fn left(&self) -> usize {
+        self.bytes.len() - self.at
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'b [u8], BinaryError> {
+        if len > self.left() {
+            return Err(BinaryError::Truncated);
+        }
+        self.at += len;
+        Ok(&self.bytes[self.at - len..self.at])
+    }
+
+    fn byte(&mut self) -> Result<u8, BinaryError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, BinaryError> {
+        let mut value = 0_u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7F) as u64;
+            if bits << shift >> shift != bits {
+                return Err(BinaryError::InvalidValue);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(BinaryError::InvalidValue)
+    }
+
+    fn signed(&mut self) -> Result<i64, BinaryError> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn len(&mut self) -> Result<usize, BinaryError> {
+        usize::try_from(self.varint()?).map_err(|_| BinaryError::InvalidValue)
+    }
+
+    fn str<'s>(&mut self, table: &[&'s str]) -> Result<&'s str, BinaryError> {
+        let index = self.len()?;
+        table.get(index).copied().ok_or(BinaryError::InvalidValue)
+    }
+
+    fn string(&mut self, table: &[&str]) -> Result<String, BinaryError> {
+        self.str(table).map(str::to_string)
+    }
+
+    fn token(&mut self, table: &[&str], (previous_end, previous_line): (usize, i32), interner: Option<&mut (dyn Intern + '_)>) -> Result<Token, BinaryError> {
+        let tag = self.byte()?;
+        let kind = *TokenKind::ALL.get(tag as usize).ok_or(BinaryError::InvalidTag(tag))?;
+        let flags = self.byte()?;
+        let start = (previous_end as i64).checked_add(self.signed()?).ok_or(BinaryError::InvalidValue)?;
+        let start = usize::try_from(start).map_err(|_| BinaryError::InvalidValue)?;
+        let end = start.checked_add(self.len()?).ok_or(BinaryError::InvalidValue)?;
+        let line = (previous_line as i64).checked_add(self.signed()?).ok_or(BinaryError::InvalidValue)?;
+        let position = TokenPosition::new(to_i32(self.signed()?)?, to_i32(line)?);
+        let text = self.string(table)?;
+
+        let token_type = match plain_type(kind) {
+            Some(plain) => plain,
+            None => match kind {
+                TokenKind::Any => TokenType::Any(self.string(table)?),
+                TokenKind::StringLiteral => TokenType::StringLiteral(self.string(table)?),
+                TokenKind::Id => TokenType::Id(self.string(table)?),
+                TokenKind::IntegerLiteral => TokenType::IntegerLiteral(self.signed()?),
+                TokenKind::FloatLiteral => TokenType::FloatLiteral(f64::from_bits(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))),
+                TokenKind::CharLiteral => {
+                    let value = u32::try_from(self.varint()?).ok().and_then(char::from_u32);
+                    TokenType::CharLiteral(value.ok_or(BinaryError::InvalidValue)?)
+                },
+                TokenKind::ExactIntegerLiteral | TokenKind::ExactDecimalLiteral => {
+                    let negative = match self.byte()? {
+                        0 => false,
+                        1 => true,
+                        tag => return Err(BinaryError::InvalidTag(tag))
+                    };
+                    let number = ExactNumber::new(negative, self.radix()?, self.string(table)?);
+                    match kind {
+                        TokenKind::ExactIntegerLiteral => TokenType::ExactIntegerLiteral(number),
+                        _ => TokenType::ExactDecimalLiteral(number)
+                    }
+                },
+                TokenKind::Symbol => {
+                    let name = self.str(table)?;
+                    match interner {
+                        Some(interner) => TokenType::Symbol(interner.intern(name)),
+                        None => TokenType::Id(name.to_string())
+                    }
+                },
+                TokenKind::Newline => TokenType::Newline(self.line_ending()?),
+                TokenKind::Error => TokenType::Error(self.error()?),
+                _ => unreachable!("{:?} has no payload", kind)
+            }
+        };
+
+        let number = if flags & HAS_NUMBER != 0 {
+            let radix = self.radix()?;
+            let suffix = match self.byte()? {
+                0 => None,
+                1 => Some(self.string(table)?),
+                tag => return Err(BinaryError::InvalidTag(tag))
+            };
+            Some(NumberLiteral { radix, suffix })
+        } else {
+            None
+        };
+        let scope = if flags & HAS_SCOPE != 0 { Some(self.signed()?) } else { None };
+        if flags & !(HAS_NUMBER | HAS_SCOPE) != 0 {
+            return Err(BinaryError::InvalidTag(flags));
+        }
+
+        Ok(Token(token_type, TokenData { text, position, span: Span::new(start, end), number, scope }))
+    }
+
+    fn radix(&mut self) -> Result<Radix, BinaryError> {
+        match self.byte()? {
+            2 => Ok(Radix::Binary),
+            8 => Ok(Radix::Octal),
+            10 => Ok(Radix::Decimal),
+            16 => Ok(Radix::Hexadecimal),
+            tag => Err(BinaryError::InvalidTag(tag))
+        }
+    }
+
+    fn line_ending(&mut self) -> Result<LineEnding, BinaryError> {
+        match self.byte()? {
+            0 => Ok(LineEnding::Lf),
+            1 => Ok(LineEnding::CrLf),
+            2 => Ok(LineEnding::Cr),
+            3 => Ok(LineEnding::LineSeparator),
+            4 => Ok(LineEnding::ParagraphSeparator),
+            tag => Err(BinaryError::InvalidTag(tag))
+        }
+    }
+
+    fn error(&mut self) -> Result<TokenizeErrorKind, BinaryError> {
+        let (tag, detail) = (self.byte()?, self.byte()?);
+        Ok(match (tag, detail) {
+            (0, 0) => TokenizeErrorKind::InconsistentIndentation,
+            (1, 0) => TokenizeErrorKind::UnmatchedDedent,
+            (2, 0) => TokenizeErrorKind::InvalidNumber,
+            (3, 0) => TokenizeErrorKind::InvalidNumberSuffix,
+            (4, 0) => TokenizeErrorKind::NumberOverflow,
+            (5, 0) => TokenizeErrorKind::InvalidEscape,
+            (6, 0) => TokenizeErrorKind::InvalidHexEscape,
+            (7, 0) => TokenizeErrorKind::InvalidUnicodeEscape,
+            (8, 0) => TokenizeErrorKind::LoneSurrogate,
+            (9, 0) => TokenizeErrorKind::UnterminatedEscape,
+            (10, 0) => TokenizeErrorKind::EmptyCharLiteral,
+            (11, 0) => TokenizeErrorKind::MultiCharLiteral,
+            (12, 0) => TokenizeErrorKind::InvalidEncoding(DecodeErrorKind::InvalidUtf8),
+            (12, 1) => TokenizeErrorKind::InvalidEncoding(DecodeErrorKind::UnpairedSurrogate),
+            (12, 2) => TokenizeErrorKind::InvalidEncoding(DecodeErrorKind::TruncatedUtf16),
+            (13, detail) => TokenizeErrorKind::Io(*IO_KINDS.get(detail as usize).ok_or(BinaryError::InvalidTag(detail))?),
+            (14, 0) => TokenizeErrorKind::UnterminatedLiteral,
+            (0..=12 | 14, detail) => return Err(BinaryError::InvalidTag(detail)),
+            (tag, _) => return Err(BinaryError::InvalidTag(tag))
+        })
+    }
+}
+
+fn line_ending_tag(line_ending: LineEnding) -> u8 {
+    match line_ending {
+        LineEnding::Lf => 0,
+        LineEnding::CrLf => 1,
+        LineEnding::Cr => 2,
+        LineEnding::LineSeparator => 3,
+        LineEnding::ParagraphSeparator => 4
+    }
+}
+
+fn error_tags(error: &TokenizeErrorKind) -> [u8; 2] {
+    match error {
+        TokenizeErrorKind::InconsistentIndentation => [0, 0],
+        TokenizeErrorKind::UnmatchedDedent => [1, 0],
+        TokenizeErrorKind::InvalidNumber => [2, 0],
+        TokenizeErrorKind::InvalidNumberSuffix => [3, 0],
+        TokenizeErrorKind::NumberOverflow => [4, 0],
+        TokenizeErrorKind::InvalidEscape => [5, 0],
+        TokenizeErrorKind::InvalidHexEscape => [6, 0],
+        TokenizeErrorKind::InvalidUnicodeEscape => [7, 0],
+        TokenizeErrorKind::LoneSurrogate => [8, 0],
+        TokenizeErrorKind::UnterminatedEscape => [9, 0],
+        TokenizeErrorKind::EmptyCharLiteral => [10, 0],
+        TokenizeErrorKind::MultiCharLiteral => [11, 0],
+        TokenizeErrorKind::InvalidEncoding(DecodeErrorKind::InvalidUtf8) => [12, 0],
+        TokenizeErrorKind::InvalidEncoding(DecodeErrorKind::UnpairedSurrogate) => [12, 1],
+        TokenizeErrorKind::InvalidEncoding(DecodeErrorKind::TruncatedUtf16) => [12, 2],
+        TokenizeErrorKind::Io(kind) => [13, IO_KINDS.iter().position(|known| known == kind).unwrap_or(0) as u8],
+        TokenizeErrorKind::UnterminatedLiteral => [14, 0]
+    }
+}
+
+fn to_i32(value: i64) -> Result<i32, BinaryError> {
+    i32::try_from(value).map_err(|_| BinaryError::InvalidValue)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_signed(out: &mut Vec<u8>, value: i64) {
+    write_varint(out, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+};
+
+/// CRC-32 as used by zip and PNG
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, byte| CRC_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{options::{self, Indentation}, tokenizer::{tokenize, tokenize_interned}, interner::Interner};
+
+    use super::*;
+
+    const SOURCE: &str = "let x = \"multi\nline \\\" string\";\r\ny = 'é' + 0x1F_u8 * -2.5e3; // 中文\r\n\n  z(a,\n b) != null \u{2028}\n  '' 99999999999999999999 \"unterminated\n";
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let mut indented = options::default();
+        indented.indentation = Some(Indentation::new(2));
+        let mut exact = options::default();
+        exact.exact_numbers = true;
+
+        for options in [options::default(), options::legacy(), indented, exact] {
+            let tokens = tokenize(SOURCE.to_string(), &options);
+            assert_eq!(decode(&encode(&tokens).unwrap()), Ok(tokens));
+        }
+        let mut interner = Interner::new();
+        let tokens = tokenize_interned(SOURCE.repeat(3), &options::default(), &mut interner);
+        assert_eq!(decode_interned(&encode_interned(&tokens, &interner).unwrap(), &mut interner), Ok(tokens));
+        assert_eq!(decode(&encode(&[]).unwrap()), Ok(vec![]));
+
+        let errors: Vec<_> = [TokenizeErrorKind::InvalidEncoding(DecodeErrorKind::TruncatedUtf16), TokenizeErrorKind::Io(io::ErrorKind::TimedOut)]
+            .into_iter()
+            .map(|kind| Token(TokenType::Error(kind), TokenData::new(String::new(), TokenPosition::default())))
+            .collect();
+        assert_eq!(decode(&encode(&errors).unwrap()), Ok(errors));
+    }
+
+    #[test]
+    fn keeps_the_format_stable() {
+        let tokens = tokenize("a = \"b\"\n".to_string(), &options::default());
+        assert_eq!(encode(&tokens).unwrap(), [
+            b'N', b'T', b'O', b'K', 2, 0, 0x15, 0xB7, 0x7C, 0x98,
+            // Strings
+            6, 1, b'a', 1, b'=', 3, b'"', b'b', b'"', 1, b'b', 1, b'\n', 0,
+            // Tokens: kind, flags, start, length, line, position, text, then payload and scope
+            5,
+            51, 0, 0, 1, 0, 2, 0, 0,
+            21, 0, 2, 1, 0, 4, 1,
+            45, 2, 2, 3, 0, 10, 2, 3, 0,
+            53, 0, 0, 1, 0, 14, 4, 0,
+            41, 0, 0, 0, 2, 0, 5
+        ]);
+    }
+
+    #[test]
+    fn writes_symbols_by_name() {
+        let mut interner = Interner::new();
+        interner.intern("unrelated");
+        let tokens = tokenize_interned("x = y".to_string(), &options::default(), &mut interner);
+        let encoded = encode_interned(&tokens, &interner).unwrap();
+
+        // Another interner gives the names its own symbols
+        let mut other = Interner::new();
+        let decoded = decode_interned(&encoded, &mut other).unwrap();
+        assert_eq!(other.iter().map(|(_, name)| name).collect::<Vec<_>>(), vec!["x", "y"]);
+        assert_eq!(decoded[0].0, TokenType::Symbol(other.get("x").unwrap()));
+        assert_eq!(decoded[2].0, TokenType::Symbol(other.get("y").unwrap()));
+
+        // Without an interner they are plain identifiers
+        let decoded = decode(&encoded).unwrap();
+        assert_eq!(decoded[2].0, TokenType::Id("y".to_string()));
+
+        // Only their interner can encode them
+        let x = interner.get("x").unwrap();
+        assert_eq!(encode(&tokens), Err(BinaryError::UnknownSymbol(x)));
+        assert_eq!(encode_interned(&tokens, &Interner::new()), Err(BinaryError::UnknownSymbol(x)));
+    }
+
+    #[test]
+    fn rejects_damaged_or_foreign_data() {
+        let encoded = encode(&tokenize(SOURCE.to_string(), &options::default())).unwrap();
+        assert_eq!(decode(b"GIF89a"), Err(BinaryError::NotTokens));
+        assert_eq!(decode(&encoded[..7]), Err(BinaryError::Truncated));
+
+        let mut newer = encoded.clone();
+        newer[4] = 3;
+        assert_eq!(decode(&newer), Err(BinaryError::UnsupportedVersion(3)));
+
+        for at in HEADER_LEN..encoded.len() {
+            let mut damaged = encoded.clone();
+            damaged[at] ^= 0x20;
+            assert!(matches!(decode(&damaged), Err(BinaryError::ChecksumMismatch { .. })), "byte {}", at);
+        }
+        assert!(matches!(decode(&encoded[..encoded.len() - 1]), Err(BinaryError::ChecksumMismatch { .. })));
+    }
+
+    #[test]
+    fn rejects_malformed_data_with_a_valid_checksum() {
+        let sealed = |body: &[u8]| [&MAGIC[..], &VERSION.to_le_bytes(), &crc32(body).to_le_bytes(), body].concat();
+        assert_eq!(decode(&sealed(&[0, 1, 5])), Err(BinaryError::Truncated));
+        assert_eq!(decode(&sealed(&[0, 1, 200, 0, 0, 0, 0, 0, 0])), Err(BinaryError::InvalidTag(200)));
+        assert_eq!(decode(&sealed(&[0, 1, 0, 0, 0, 0, 0, 0, 0])), Err(BinaryError::InvalidValue));
+        assert_eq!(decode(&sealed(&[1, 1, b'a', 1, 0, 0, 1, 0, 0, 0, 0])), Err(BinaryError::InvalidValue));
+        assert_eq!(decode(&sealed(&[0, 0, 0])), Err(BinaryError::TrailingBytes));
+        assert_eq!(decode(&sealed(&[0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01])), Err(BinaryError::InvalidValue));
+
+        // Whatever the bytes, decoding gives an error instead of panicking
+        let encoded = encode(&tokenize(SOURCE.to_string(), &options::default())).unwrap();
+        let mut state = 0x9E37_79B9_7F4A_7C15_u64;
+        for _ in 0..2000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let mut body = encoded[HEADER_LEN..].to_vec();
+            let at = state as usize % body.len();
+            body[at] = (state >> 32) as u8;
+            let _ = decode(&sealed(&body));
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{token::{Token, TokenPosition, Span}, token_buffer::TokenKind, error::ParseError};
+
+/// A place in the tokens of a `TokenCursor` to come back to with `rewind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Checkpoint(usize);
+
+/// Walks a slice of tokens for a parser, looking ahead, matching kinds and reporting what it did not expect.
+///
+/// Tokens of the trivia kinds, none unless set with `with_trivia`, are skipped by every method but `remaining`.
+#[derive(Debug, Clone)]
+pub struct TokenCursor<'t> {
+    tokens: &'t [Token],
+    index: usize,
+    trivia: Vec<TokenKind>
+}
+impl<'t> TokenCursor<'t> {
+    pub fn new(tokens: &'t [Token]) -> Self {
+        TokenCursor {
+            tokens,
+            index: 0,
+            trivia: vec![]
+        }
+    }
+
+    /// Skips tokens of these kinds from now on, such as `Newline` for parsers of free-form languages
+    pub fn with_trivia(mut self, trivia: &[TokenKind]) -> Self {
+        self.trivia = trivia.to_vec();
+        self
+    }
+
+    fn is_trivia(&self, token: &Token) -> bool {
+        self.trivia.contains(&TokenKind::of(&token.0))
+    }
+
+    /// Moves past the trivia before the next token
+    pub fn skip_trivia(&mut self) {
+        while self.tokens.get(self.index).is_some_and(|token| self.is_trivia(token)) {
+            self.index += 1;
+        }
+    }
+
+    /// The token `n` places after the next one, without moving, `peek(0)` being the next token
+    pub fn peek(&self, n: usize) -> Option<&'t Token> {
+        self.tokens[self.index..].iter().filter(|token| !self.is_trivia(token)).nth(n)
+    }
+
+    /// Kind of the token `n` places after the next one
+    pub fn peek_kind(&self, n: usize) -> Option<TokenKind> {
+        self.peek(n).map(|token| TokenKind::of(&token.0))
+    }
+
+    /// Whether the next token is of `kind`
+    pub fn check(&self, kind: TokenKind) -> bool {
+        self.peek_kind(0) == Some(kind)
+    }
+
+    /// Moves past the next token if it is of `kind`, giving it
+    pub fn eat(&mut self, kind: TokenKind) -> Option<&'t Token> {
+        if self.check(kind) { self.next() } else { None }
+    }
+
+    /// Moves past the next token if it is of `kind`, failing with what was found instead otherwise
+    pub fn expect(&mut self, kind: TokenKind) -> Result<&'t Token, ParseError> {
+        self.eat(kind).ok_or_else(|| self.unexpected(&[kind]))
+    }
+
+    /// Moves past the next token if it is of one of `kinds`, failing with what was found instead otherwise
+    pub fn expect_one_of(&mut self, kinds: &[TokenKind]) -> Result<&'t Token, ParseError> {
+        match self.peek_kind(0) {
+            Some(kind) if kinds.contains(&kind) => Ok(self.next().unwrap()),
+            _ => Err(self.unexpected(kinds))
+        }
+    }
+
+    /// An error about the next token, which is none of the `expected` kinds
+    pub fn unexpected(&self, expected: &[TokenKind]) -> ParseError {
+        let found = self.peek(0);
+        ParseError {
+            expected: expected.to_vec(),
+            found: found.map(|token| TokenKind::of(&token.0)),
+            position: self.position(),
+            span: found.map_or_else(|| self.end_span(), |token| token.1.span)
+        }
+    }
+
+    /// Position of the next token, or of the last one at the end
+    pub fn position(&self) -> TokenPosition {
+        self.peek(0).or(self.tokens.last()).map_or_else(TokenPosition::default, |token| token.1.position)
+    }
+
+    fn end_span(&self) -> Span {
+        let end = self.tokens.last().map_or(0, |token| token.1.span.end);
+        Span::new(end, end)
+    }
+
+    /// Whether only trivia or `EndOfInput` is left
+    pub fn is_at_end(&self) -> bool {
+        matches!(self.peek_kind(0), None | Some(TokenKind::EndOfInput))
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.index)
+    }
+
+    /// Goes back, or forward, to where `checkpoint` was taken
+    pub fn rewind(&mut self, checkpoint: Checkpoint) {
+        self.index = checkpoint.0.min(self.tokens.len());
+    }
+
+    /// The tokens not yet read, trivia included
+    pub fn remaining(&self) -> &'t [Token] {
+        &self.tokens[self.index..]
+    }
+}
+impl<'t> Iterator for TokenCursor<'t> {
+    type Item = &'t Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.skip_trivia();
+        let token = self.tokens.get(self.index)?;
+        self.index += 1;
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{options, tokenizer::tokenize, token::TokenType};
+
+    use super::*;
+
+    /// A call such as `f(a, g(b))`, as the name and the arguments
+    #[derive(Debug, PartialEq)]
+    enum Expr {
+        Name(String),
+        Call(String, Vec<Expr>)
+    }
+
+    fn expr(cursor: &mut TokenCursor) -> Result<Expr, ParseError> {
+        let name = match &cursor.expect(TokenKind::Id)?.0 {
+            TokenType::Id(name) => name.clone(),
+            _ => unreachable!()
+        };
+        if cursor.eat(TokenKind::OpenParenthesis).is_none() {
+            return Ok(Expr::Name(name));
+        }
+        let mut arguments = vec![];
+        if cursor.eat(TokenKind::CloseParenthesis).is_some() {
+            return Ok(Expr::Call(name, arguments));
+        }
+        loop {
+            arguments.push(expr(cursor)?);
+            if cursor.expect_one_of(&[TokenKind::Comma, TokenKind::CloseParenthesis])?.0 == TokenType::CloseParenthesis {
+                return Ok(Expr::Call(name, arguments));
+            }
+        }
+    }
+
+    fn parse(source: &str) -> Result<Expr, ParseError> {
+        let tokens = tokenize(source.to_string(), &options::default());
+        let mut cursor = TokenCursor::new(&tokens).with_trivia(&[TokenKind::Newline]);
+        let parsed = expr(&mut cursor)?;
+        if !cursor.is_at_end() {
+            return Err(cursor.unexpected(&[]));
+        }
+        Ok(parsed)
+    }
+
+    #[test]
+    fn parses_by_descent() {
+        let name = |name: &str| Expr::Name(name.to_string());
+        assert_eq!(parse("f(a,\n g(b), h())\n"), Ok(Expr::Call("f".to_string(), vec![
+            name("a"),
+            Expr::Call("g".to_string(), vec![name("b")]),
+            Expr::Call("h".to_string(), vec![])
+        ])));
+    }
+
+    #[test]
+    fn reports_what_was_expected_where() {
+        let err = parse("f(a\n  b)").unwrap_err();
+        assert_eq!(err.expected, vec![TokenKind::Comma, TokenKind::CloseParenthesis]);
+        assert_eq!(err.found, Some(TokenKind::Id));
+        assert_eq!(err.span, Span::new(6, 7));
+        assert_eq!(err.to_string(), "expected one of Comma, CloseParenthesis, found Id at line 2, position 3");
+
+        assert_eq!(parse("f(").unwrap_err().to_string(), "expected Id, found EndOfInput at line 1, position 2");
+        assert_eq!(parse("f g").unwrap_err().to_string(), "unexpected Id at line 1, position 3");
+
+        let cursor = TokenCursor::new(&[]);
+        assert_eq!(cursor.unexpected(&[TokenKind::Id]).to_string(), "expected Id, found end of input at line 1, position 0");
+    }
+
+    #[test]
+    fn looks_ahead_and_backtracks() {
+        let tokens = tokenize("a\n= b".to_string(), &options::default());
+        let mut cursor = TokenCursor::new(&tokens).with_trivia(&[TokenKind::Newline]);
+        assert_eq!(cursor.peek_kind(1), Some(TokenKind::Equal));
+        assert_eq!(cursor.peek_kind(3), Some(TokenKind::EndOfInput));
+        assert_eq!(cursor.peek(4), None);
+
+        let start = cursor.checkpoint();
+        assert!(cursor.eat(TokenKind::Id).is_some());
+        assert!(cursor.eat(TokenKind::Id).is_none());
+        assert!(cursor.expect(TokenKind::Equal).is_ok());
+        cursor.rewind(start);
+        assert!(cursor.check(TokenKind::Id));
+        assert_eq!(cursor.by_ref().count(), 4);
+        assert!(cursor.is_at_end());
+        assert!(cursor.remaining().is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt::{Display, Formatter};
+
+/// Most zeros `Display` pads a number with before it switches to scientific notation
+const PLAIN_ZEROS_LIMIT: u64 = 64;
+
+/// An exact decimal number of any size, `coefficient × 10^exponent`
+#[derive(Debug, Clone, Eq)]
+pub struct Decimal {
+    negative: bool,
+    /// Decimal digits without leading zeros, empty for zero
+    coefficient: String,
+    exponent: i64
+}
+impl Decimal {
+    /// Parses decimal notation such as `-12.50`, `6.02e23` or `1E-9`
+    pub fn parse(text: &str) -> Option<Decimal> {
+        let (negative, unsigned) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text))
+        };
+        let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+            Some(e) => (&unsigned[..e], unsigned[e + 1..].parse::<i64>().ok()?),
+            None => (unsigned, 0)
+        };
+        let (integer, fraction) = match mantissa.split_once('.') {
+            Some((integer, fraction)) => (integer, fraction),
+            None => (mantissa, "")
+        };
+        if integer.is_empty() && fraction.is_empty() {
+            return None;
+        }
+        if !integer.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let digits = format!("{}{}", integer, fraction);
+        let coefficient = digits.trim_start_matches('0');
+        let exponent = exponent.checked_sub(fraction.len() as i64)?;
+        // Keeps the exponent in range once trailing zeros are moved into it
+        exponent.checked_add((coefficient.len() - coefficient.trim_end_matches('0').len()) as i64)?;
+        Some(Decimal {
+            negative,
+            coefficient: coefficient.to_string(),
+            exponent
+        })
+    }
+
+    pub fn from_u128(value: u128, negative: bool) -> Decimal {
+        Decimal {
+            negative,
+            coefficient: if value == 0 { String::new() } else { value.to_string() },
+            exponent: 0
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coefficient.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative && !self.is_zero()
+    }
+
+    /// Digits of the number without its sign and decimal point
+    pub fn coefficient(&self) -> &str {
+        if self.is_zero() { "0" } else { &self.coefficient }
+    }
+
+    pub fn exponent(&self) -> i64 {
+        self.exponent
+    }
+
+    /// Checks whether the number has no fractional part
+    pub fn is_integer(&self) -> bool {
+        let normalized = self.normalized();
+        normalized.is_zero() || normalized.exponent >= 0
+    }
+
+    /// Gets the number as an integer, if it has no fractional part and fits
+    pub fn to_i128(&self) -> Option<i128> {
+        if !self.is_integer() {
+            return None;
+        }
+        let normalized = self.normalized();
+        let mut magnitude: u128 = if normalized.is_zero() { 0 } else { normalized.coefficient.parse().ok()? };
+        for _ in 0..normalized.exponent.max(0) {
+            magnitude = magnitude.checked_mul(10)?;
+        }
+        if self.negative { 0i128.checked_sub_unsigned(magnitude) } else { i128::try_from(magnitude).ok() }
+    }
+
+    /// Gets the closest `f64` to the number
+    pub fn to_f64(&self) -> f64 {
+        format!("{}{}e{}", if self.negative { "-" } else { "" }, self.coefficient(), self.exponent)
+            .parse()
+            .unwrap_or(f64::NAN)
+    }
+
+    /// Same number with trailing zeros moved into the exponent
+    fn normalized(&self) -> Decimal {
+        let trimmed = self.coefficient.trim_end_matches('0');
+        Decimal {
+            negative: self.negative && !trimmed.is_empty(),
+            coefficient: trimmed.to_string(),
+            exponent: if trimmed.is_empty() { 0 } else { self.exponent + (self.coefficient.len() - trimmed.len()) as i64 }
+        }
+    }
+}
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Decimal) -> bool {
+        let (left, right) = (self.normalized(), other.normalized());
+        left.negative == right.negative && left.coefficient == right.coefficient && left.exponent == right.exponent
+    }
+}
+impl Display for Decimal {
+    /// Writes the number in plain notation, keeping the precision it was written with.
+    ///
+    /// Numbers that would need more than 64 padding zeros are written as `coefficient` `e` `exponent` instead.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_negative() {
+            write!(f, "-")?;
+        }
+        let digits = self.coefficient();
+        let padding = if self.exponent >= 0 {
+            self.exponent.unsigned_abs()
+        } else {
+            self.exponent.unsigned_abs().saturating_sub(digits.len() as u64)
+        };
+        if !self.is_zero() && padding > PLAIN_ZEROS_LIMIT {
+            write!(f, "{}e{}", digits, self.exponent)
+        } else if self.exponent >= 0 {
+            write!(f, "{}{}", digits, "0".repeat(if self.is_zero() { 0 } else { self.exponent as usize }))
+        } else {
+            let scale = self.exponent.unsigned_abs() as usize;
+            if digits.len() > scale {
+                let (integer, fraction) = digits.split_at(digits.len() - scale);
+                write!(f, "{}.{}", integer, fraction)
+            } else {
+                write!(f, "0.{}{}", "0".repeat(scale - digits.len()), digits)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_exactly() {
+        let value = Decimal::parse("0.1").unwrap();
+        assert_eq!(value.coefficient(), "1");
+        assert_eq!(value.exponent(), -1);
+        assert_eq!(value.to_string(), "0.1");
+        assert_eq!(Decimal::parse("-12.50").unwrap().to_string(), "-12.50");
+        assert_eq!(Decimal::parse("6.02e23").unwrap().to_string(), "602000000000000000000000");
+        assert_eq!(Decimal::parse("1e-9").unwrap().to_string(), "0.000000001");
+    }
+
+    #[test]
+    fn compares_by_value() {
+        assert_eq!(Decimal::parse("1.50").unwrap(), Decimal::parse("1.5").unwrap());
+        assert_eq!(Decimal::parse("150e-2").unwrap(), Decimal::parse("1.5").unwrap());
+        assert_eq!(Decimal::parse("-0.0").unwrap(), Decimal::parse("0").unwrap());
+        assert_ne!(Decimal::parse("0.1").unwrap(), Decimal::parse("0.10000000000000001").unwrap());
+    }
+
+    #[test]
+    fn converts_to_integers_and_floats() {
+        assert_eq!(Decimal::parse("12.000").unwrap().to_i128(), Some(12));
+        assert_eq!(Decimal::parse("1.5").unwrap().to_i128(), None);
+        assert_eq!(Decimal::parse("1e40").unwrap().to_i128(), None);
+        assert_eq!(Decimal::parse(&i128::MIN.to_string()).unwrap().to_i128(), Some(i128::MIN));
+        assert_eq!(Decimal::parse(&i128::MAX.to_string()).unwrap().to_i128(), Some(i128::MAX));
+        assert_eq!(Decimal::parse("170141183460469231731687303715884105728").unwrap().to_i128(), None);
+        assert_eq!(Decimal::parse("-2.5").unwrap().to_f64(), -2.5);
+    }
+
+    #[test]
+    fn writes_huge_exponents_in_scientific_notation() {
+        assert_eq!(Decimal::parse("1e9223372036854775807").unwrap().to_string(), "1e9223372036854775807");
+        assert_eq!(Decimal::parse("-2.5e-9223372036854775800").unwrap().to_string(), "-25e-9223372036854775801");
+        assert_eq!(Decimal::parse("0e9223372036854775807").unwrap().to_string(), "0");
+        assert_eq!(Decimal::parse("1e64").unwrap().to_string(), format!("1{}", "0".repeat(64)));
+        assert_eq!(Decimal::parse("1e65").unwrap().to_string(), "1e65");
+        assert_eq!(Decimal::parse("10e9223372036854775807"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_text() {
+        assert_eq!(Decimal::parse("."), None);
+        assert_eq!(Decimal::parse("1.2.3"), None);
+        assert_eq!(Decimal::parse("1e"), None);
+    }
+}
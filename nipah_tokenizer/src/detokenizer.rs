@@ -0,0 +1,385 @@
+use crate::{token::{Token, TokenType, TokenData}, options::LineEnding, interner::Interner};
+
+pub struct PrintOptions {
+    /// Text written once per nesting level at the start of each line
+    pub indent: String,
+    /// Break lines after `;` and `{` and before `}`
+    pub line_breaks: bool,
+    /// Wrap lines longer than this many characters at their last comma
+    pub max_width: Option<usize>,
+    /// Collapse longer runs of empty lines into this many
+    pub max_blank_lines: Option<usize>,
+    /// Write every line break like this, instead of the way the source wrote it
+    pub line_ending: Option<LineEnding>
+}
+impl Default for PrintOptions {
+    fn default() -> Self {
+        PrintOptions {
+            indent: "    ".to_string(),
+            line_breaks: true,
+            max_width: None,
+            max_blank_lines: None,
+            line_ending: None
+        }
+    }
+}
+
+/// Turns tokens back into source text.
+///
+/// Tokenizing the output with the default configuration yields the same tokens again,
+/// except for the `Newline` tokens of any line breaks inserted by `PrintOptions::line_breaks`.
+/// `Symbol` tokens keep no text, so they are written by `detokenize_interned`.
+pub fn detokenize(tokens: &[Token], options: &PrintOptions) -> String {
+    let mut printer = Printer::new(options);
+    for token in tokens {
+        printer.push(token);
+    }
+    printer.finish()
+}
+
+/// Turns tokens back into source text as `detokenize`, writing `Symbol` tokens as their names in `interner`
+pub fn detokenize_interned(tokens: &[Token], options: &PrintOptions, interner: &Interner) -> String {
+    let mut printer = Printer::new(options);
+    printer.interner = Some(interner);
+    for token in tokens {
+        printer.push(token);
+    }
+    printer.finish()
+}
+
+pub(crate) struct Printer<'o> {
+    options: &'o PrintOptions,
+    interner: Option<&'o Interner>,
+    output: String,
+    previous: Option<TokenType>,
+    depth: usize,
+    parenthesis_depth: usize,
+    at_line_start: bool,
+    in_line_comment: bool,
+    blank_lines: usize,
+    line_start: usize,
+    wrap_point: Option<usize>,
+    line_ending: LineEnding
+}
+impl<'o> Printer<'o> {
+    pub(crate) fn new(options: &'o PrintOptions) -> Self {
+        Printer {
+            options,
+            interner: None,
+            output: String::new(),
+            previous: None,
+            depth: 0,
+            parenthesis_depth: 0,
+            at_line_start: true,
+            in_line_comment: false,
+            blank_lines: 0,
+            line_start: 0,
+            wrap_point: None,
+            line_ending: options.line_ending.unwrap_or(LineEnding::Lf)
+        }
+    }
+
+    pub(crate) fn push(&mut self, token: &Token) {
+        // Interned identifiers are written and spaced as any other identifier
+        let interned;
+        let token = match &token.0 {
+            TokenType::Symbol(symbol) => {
+                let name = self.interner.map_or(token.1.text.as_str(), |interner| interner.resolve(*symbol));
+                interned = Token(TokenType::Id(name.to_string()), TokenData { text: name.to_string(), ..token.1.clone() });
+                &interned
+            },
+            _ => token
+        };
+        let kind = &token.0;
+
+        match kind {
+            TokenType::EOF | TokenType::Newline(_) => {
+                let ending = match kind {
+                    TokenType::Newline(ending) => Some(*ending),
+                    _ => LineEnding::at(&token.1.text)
+                };
+                if let (None, Some(ending)) = (self.options.line_ending, ending) {
+                    self.line_ending = ending;
+                }
+                self.newline();
+                self.previous = Some(kind.clone());
+                return;
+            },
+            TokenType::EndOfInput => return,
+            TokenType::Indent => {
+                self.depth += 1;
+                return;
+            },
+            TokenType::Dedent => {
+                self.depth = self.depth.saturating_sub(1);
+                return;
+            },
+            _ => {}
+        }
+
+        if *kind == TokenType::CloseCurlyBrace {
+            self.depth = self.depth.saturating_sub(1);
+        }
+        if matches!(kind, TokenType::CloseParenthesis | TokenType::CloseBracket) {
+            self.parenthesis_depth = self.parenthesis_depth.saturating_sub(1);
+        }
+
+        if !self.at_line_start {
+            if self.should_break_before(kind) {
+                self.newline();
+            } else if let Some(previous) = &self.previous {
+                if needs_space(previous, kind) {
+                    if *previous == TokenType::Comma && !self.in_line_comment {
+                        self.wrap_point = Some(self.output.len());
+                    }
+                    self.output.push(' ');
+                }
+            }
+        }
+        self.write(&token_source(token));
+
+        match kind {
+            TokenType::OpenCurlyBrace => self.depth += 1,
+            TokenType::OpenParenthesis | TokenType::OpenBracket => self.parenthesis_depth += 1,
+            TokenType::SingleLineComment => self.in_line_comment = true,
+            _ => {}
+        }
+        self.previous = Some(kind.clone());
+    }
+
+    /// Writes a comment exactly as it appears in the source
+    pub(crate) fn push_comment(&mut self, text: &str, is_line_comment: bool) {
+        if !self.at_line_start {
+            self.output.push(' ');
+        }
+        self.write(text);
+        if is_line_comment {
+            self.in_line_comment = true;
+        }
+    }
+
+    /// The line break written by the last newline
+    pub(crate) fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    pub(crate) fn finish(self) -> String {
+        self.output
+    }
+
+    fn write(&mut self, text: &str) {
+        if self.at_line_start {
+            let levels = self.depth + if self.parenthesis_depth > 0 { 1 } else { 0 };
+            for _ in 0..levels {
+                self.output.push_str(&self.options.indent);
+            }
+            self.at_line_start = false;
+            self.blank_lines = 0;
+        }
+        self.output.push_str(text);
+
+        if let (Some(max_width), Some(wrap_point)) = (self.options.max_width, self.wrap_point) {
+            if self.output[self.line_start..].chars().count() > max_width {
+                let ending = self.line_ending.as_str();
+                let continuation = format!("{}{}", ending, self.options.indent.repeat(self.depth + 1));
+                self.output.replace_range(wrap_point..wrap_point + 1, &continuation);
+                self.line_start = wrap_point + ending.len();
+                self.wrap_point = None;
+            }
+        }
+    }
+
+    fn should_break_before(&self, next: &TokenType) -> bool {
+        if !self.options.line_breaks || self.in_line_comment {
+            return false;
+        }
+        match (&self.previous, next) {
+            (Some(TokenType::Semicolon), _) => self.parenthesis_depth == 0,
+            (Some(TokenType::OpenCurlyBrace), _) => true,
+            (_, TokenType::CloseCurlyBrace) => true,
+            _ => false
+        }
+    }
+
+    fn newline(&mut self) {
+        if self.at_line_start {
+            self.blank_lines += 1;
+            if matches!(self.options.max_blank_lines, Some(max) if self.blank_lines > max) {
+                return;
+            }
+        }
+        self.output.push_str(self.line_ending.as_str());
+        self.at_line_start = true;
+        self.in_line_comment = false;
+        self.line_start = self.output.len();
+        self.wrap_point = None;
+    }
+}
+
+/// Gets the text of a token as it would appear in the source, quoting and escaping literals again
+pub fn token_source(token: &Token) -> String {
+    match &token.0 {
+        TokenType::StringLiteral(value) => quote(value, &token.1.text),
+        TokenType::CharLiteral(value) => quote(&value.to_string(), &token.1.text),
+        _ => token.1.text.clone()
+    }
+}
+
+fn quote(value: &str, text: &str) -> String {
+    let begin = text.chars().next().unwrap_or('"');
+    let end = text.chars().last().unwrap_or(begin);
+
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push(begin);
+    quoted.push_str(&escape(value, |c| c == end));
+    quoted.push(end);
+    quoted
+}
+
+fn escape(text: &str, must_escape: impl Fn(char) -> bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\0' => escaped.push_str("\\0"),
+            c if must_escape(c) => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+fn is_punctuation(kind: &TokenType) -> bool {
+    !matches!(kind, TokenType::None | TokenType::Any(_) | TokenType::Id(_)
+        | TokenType::TrueLiteral | TokenType::FalseLiteral | TokenType::NullLiteral
+        | TokenType::StringLiteral(_) | TokenType::IntegerLiteral(_) | TokenType::FloatLiteral(_) | TokenType::CharLiteral(_)
+        | TokenType::ExactIntegerLiteral(_) | TokenType::ExactDecimalLiteral(_)
+        | TokenType::Modulo)
+}
+
+fn is_binary_operator(kind: &TokenType) -> bool {
+    matches!(kind, TokenType::Plus | TokenType::Minus | TokenType::Multiply | TokenType::Divide | TokenType::Modulo
+        | TokenType::Equal | TokenType::EqualTo | TokenType::NotEqual
+        | TokenType::LessThan | TokenType::LessThanOrEqual | TokenType::GreaterThan | TokenType::GreaterThanOrEqual
+        | TokenType::And | TokenType::Or | TokenType::Arrow | TokenType::FatArrow)
+}
+
+fn is_number(kind: &TokenType) -> bool {
+    matches!(kind, TokenType::IntegerLiteral(_) | TokenType::FloatLiteral(_)
+        | TokenType::ExactIntegerLiteral(_) | TokenType::ExactDecimalLiteral(_))
+}
+
+/// Words that read as statements rather than calls when followed by `(`
+const CONTROL_KEYWORDS: [&str; 8] = ["if", "while", "for", "foreach", "switch", "match", "return", "catch"];
+
+fn needs_space(previous: &TokenType, next: &TokenType) -> bool {
+    // Words can only be told apart by whitespace, so this rule comes before any style
+    if !is_punctuation(previous) && !is_punctuation(next) {
+        return true;
+    }
+    // A dot touching a number would be read as part of it
+    if (*previous == TokenType::Dot && is_number(next)) || (is_number(previous) && *next == TokenType::Dot) {
+        return true;
+    }
+
+    match next {
+        TokenType::Comma | TokenType::Semicolon | TokenType::Colon | TokenType::Dot
+        | TokenType::CloseParenthesis | TokenType::CloseBracket => return false,
+        _ => {}
+    }
+    match previous {
+        TokenType::OpenParenthesis | TokenType::OpenBracket | TokenType::Dot
+        | TokenType::AtSign | TokenType::DollarSign | TokenType::HashSign | TokenType::Exclamation => return false,
+        _ => {}
+    }
+    if is_binary_operator(previous) || is_binary_operator(next) {
+        return true;
+    }
+    match next {
+        TokenType::OpenParenthesis | TokenType::OpenBracket => match previous {
+            TokenType::Id(id) => CONTROL_KEYWORDS.contains(&id.as_str()),
+            TokenType::CloseParenthesis | TokenType::CloseBracket | TokenType::QuestionMark => false,
+            _ => true
+        },
+        TokenType::QuestionMark | TokenType::Exclamation => !matches!(previous,
+            TokenType::Id(_) | TokenType::CloseParenthesis | TokenType::CloseBracket),
+        _ => true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tokenizer::tokenize;
+
+    use super::*;
+
+    fn kinds_and_texts(tokens: &[Token]) -> Vec<(TokenType, String)> {
+        tokens.iter()
+            .filter(|token| !matches!(token.0, TokenType::Newline(_)))
+            .map(|token| (token.0.clone(), token.1.text.clone()))
+            .collect()
+    }
+
+    fn assert_round_trip(source: &str) {
+        let options = crate::options::default();
+        let tokens = tokenize(source.to_string(), &options);
+
+        let printed = detokenize(&tokens, &PrintOptions::default());
+        let reparsed = tokenize(printed.clone(), &options);
+        assert_eq!(kinds_and_texts(&tokens), kinds_and_texts(&reparsed), "printed as {:?}", printed);
+
+        let compact = PrintOptions { line_breaks: false, ..PrintOptions::default() };
+        let printed = detokenize(&tokens, &compact);
+        let reparsed = tokenize(printed.clone(), &options);
+        let strip = |tokens: Vec<Token>| tokens.into_iter().map(|token| (token.0, token.1.text)).collect::<Vec<_>>();
+        assert_eq!(strip(tokens), strip(reparsed), "printed as {:?}", printed);
+    }
+
+    #[test]
+    fn can_detokenize() {
+        let tokens = tokenize("fn main(a,b){let x=a+b;call(x,\"hi\");}".to_string(), &crate::options::default());
+        let text = detokenize(&tokens, &PrintOptions::default());
+        assert_eq!(text, "fn main(a, b) {\n    let x = a + b;\n    call(x, \"hi\");\n}");
+    }
+
+    #[test]
+    fn detokenize_escapes_literals() {
+        let tokens = tokenize("say(\"a\\\"b\\n\", 'x', '\\'')".to_string(), &crate::options::default());
+        let text = detokenize(&tokens, &PrintOptions::default());
+        assert_eq!(text, "say(\"a\\\"b\\n\", 'x', '\\'')");
+    }
+
+    #[test]
+    fn detokenize_keeps_line_comments_on_their_line() {
+        let tokens = tokenize("// a; b\nc;".to_string(), &crate::options::default());
+        let text = detokenize(&tokens, &PrintOptions::default());
+        assert_eq!(text, "// a; b\nc;");
+    }
+
+    #[test]
+    fn detokenize_prints_symbols_as_identifiers() {
+        let source = "if (a) { return(b)?; }";
+        let mut interner = crate::interner::Interner::new();
+        let tokens = crate::tokenizer::tokenize_interned(source.to_string(), &crate::options::default(), &mut interner);
+        let plain = tokenize(source.to_string(), &crate::options::default());
+        assert_eq!(detokenize_interned(&tokens, &PrintOptions::default(), &interner), detokenize(&plain, &PrintOptions::default()));
+    }
+
+    #[test]
+    fn detokenize_round_trips() {
+        assert_round_trip("Hello, World!");
+        assert_round_trip("if (a >= -1.5 && b != c) { x = y => z; } else { arr[0] = 'c'; }");
+        assert_round_trip("for (i = 0; i < 10; i = i + 1) {\n\tprint(\"tab\\tand \\\\ slash\");\n}\n");
+        assert_round_trip("a = = b === c -> d ;; $e @f #g ?h %i");
+        assert_round_trip("x=-5-3 // comment with 'quotes'\n/* block */ y = 1.5f - -2");
+        assert_round_trip("escaped\\ word and \"unterminated");
+        assert_round_trip("1 .1");
+        assert_round_trip("1.\t0x1F");
+    }
+}
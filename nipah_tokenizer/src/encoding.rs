@@ -0,0 +1,108 @@
+use crate::error::{DecodeError, DecodeErrorKind};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1, every byte is the code point of the same value
+    Latin1,
+    /// Latin-1 with printable characters in place of most C1 controls
+    Windows1252
+}
+
+pub(crate) const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16_LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16_BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// Characters for bytes `0x80..=0x9F` in Windows-1252, the undefined ones keep their C1 control
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}'
+];
+
+/// Finds the encoding announced by a byte order mark, along with the length of the mark
+pub fn detect_bom(bytes: &[u8]) -> Option<(Encoding, usize)> {
+    if bytes.starts_with(UTF8_BOM) {
+        Some((Encoding::Utf8, UTF8_BOM.len()))
+    } else if bytes.starts_with(UTF16_LE_BOM) {
+        Some((Encoding::Utf16Le, UTF16_LE_BOM.len()))
+    } else if bytes.starts_with(UTF16_BE_BOM) {
+        Some((Encoding::Utf16Be, UTF16_BE_BOM.len()))
+    } else {
+        None
+    }
+}
+
+/// Decodes bytes into text, following a byte order mark if there is one and using `fallback` otherwise.
+///
+/// The byte order mark is not part of the text. Error offsets count from the start of `bytes`, mark included.
+pub fn decode(bytes: &[u8], fallback: Encoding) -> Result<String, DecodeError> {
+    let (encoding, bom) = detect_bom(bytes).unwrap_or((fallback, 0));
+    decode_as(&bytes[bom..], encoding).map_err(|err| DecodeError::new(err.kind, err.offset + bom))
+}
+
+/// Decodes bytes into text with the given encoding, leaving any byte order mark in the text
+pub fn decode_as(bytes: &[u8], encoding: Encoding) -> Result<String, DecodeError> {
+    match encoding {
+        Encoding::Utf8 => std::str::from_utf8(bytes)
+            .map(str::to_string)
+            .map_err(|err| DecodeError::new(DecodeErrorKind::InvalidUtf8, err.valid_up_to())),
+        Encoding::Utf16Le => decode_utf16(bytes, u16::from_le_bytes),
+        Encoding::Utf16Be => decode_utf16(bytes, u16::from_be_bytes),
+        Encoding::Latin1 => Ok(bytes.iter().map(|b| *b as char).collect()),
+        Encoding::Windows1252 => Ok(bytes.iter()
+            .map(|b| match b {
+                0x80..=0x9F => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+                _ => *b as char
+            })
+            .collect())
+    }
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Result<String, DecodeError> {
+    if bytes.len() % 2 != 0 {
+        return Err(DecodeError::new(DecodeErrorKind::TruncatedUtf16, bytes.len() - 1));
+    }
+
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    let mut text = String::with_capacity(bytes.len() / 2);
+    let mut offset = 0;
+    for c in char::decode_utf16(units) {
+        match c {
+            Ok(c) => {
+                text.push(c);
+                offset += c.len_utf16() * 2;
+            },
+            Err(_) => return Err(DecodeError::new(DecodeErrorKind::UnpairedSurrogate, offset))
+        }
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_byte_order_marks() {
+        assert_eq!(decode(b"\xEF\xBB\xBFa = 1", Encoding::Latin1), Ok("a = 1".to_string()));
+        assert_eq!(decode(b"\xFF\xFEa\x00\x3D\xD8\x00\xDE", Encoding::Utf8), Ok("a😀".to_string()));
+        assert_eq!(decode(b"\xFE\xFF\x00a\x00\xE9", Encoding::Utf8), Ok("aé".to_string()));
+    }
+
+    #[test]
+    fn decodes_single_byte_encodings() {
+        assert_eq!(decode(b"caf\xE9 \x80", Encoding::Latin1), Ok("café \u{80}".to_string()));
+        assert_eq!(decode(b"caf\xE9 \x80\x93\x81", Encoding::Windows1252), Ok("café €“\u{81}".to_string()));
+    }
+
+    #[test]
+    fn reports_byte_offsets() {
+        assert_eq!(decode(b"\xEF\xBB\xBFab\xFFc", Encoding::Utf8), Err(DecodeError::new(DecodeErrorKind::InvalidUtf8, 5)));
+        assert_eq!(decode(b"\xFF\xFEa\x00\x00\xDCb\x00", Encoding::Utf8), Err(DecodeError::new(DecodeErrorKind::UnpairedSurrogate, 4)));
+        assert_eq!(decode_as(b"a\x00b", Encoding::Utf16Le), Err(DecodeError::new(DecodeErrorKind::TruncatedUtf16, 2)));
+    }
+}
@@ -0,0 +1,243 @@
+use std::fmt::{Display, Formatter};
+
+use crate::{token::{TokenPosition, Span}, token_buffer::TokenKind, token_tree::Delimiter, interner::Symbol};
+
+/// Kinds of IO errors kept when tokens are encoded or serialized, any other is read back as `Other`
+pub(crate) const IO_KINDS: [std::io::ErrorKind; 9] = [
+    std::io::ErrorKind::Other, std::io::ErrorKind::NotFound, std::io::ErrorKind::PermissionDenied,
+    std::io::ErrorKind::InvalidInput, std::io::ErrorKind::InvalidData, std::io::ErrorKind::UnexpectedEof,
+    std::io::ErrorKind::Interrupted, std::io::ErrorKind::TimedOut, std::io::ErrorKind::WouldBlock
+];
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TokenizeErrorKind {
+    /// Tabs and spaces were mixed so that the indentation depends on the tab width
+    InconsistentIndentation,
+    /// A line was dedented to a level that does not match any enclosing block
+    UnmatchedDedent,
+    /// A numeric literal with no digits or with digits not allowed by its radix
+    InvalidNumber,
+    /// A numeric literal followed by something that is not a known type suffix
+    InvalidNumberSuffix,
+    /// A numeric literal too large for its type
+    NumberOverflow,
+    /// A backslash followed by a character with no escape meaning
+    InvalidEscape,
+    /// A `\x` escape without two hexadecimal digits
+    InvalidHexEscape,
+    /// A malformed `\u` escape, or one outside the Unicode range
+    InvalidUnicodeEscape,
+    /// A `\u` escape of a UTF-16 surrogate that is not part of a valid pair
+    LoneSurrogate,
+    /// A backslash at the very end of the input
+    UnterminatedEscape,
+    /// A char literal with nothing between its quotes
+    EmptyCharLiteral,
+    /// A char literal holding more than one character
+    MultiCharLiteral,
+    /// A string or char literal still open at the end of the input
+    UnterminatedLiteral,
+    /// Input bytes that are not valid text
+    InvalidEncoding(DecodeErrorKind),
+    /// Reading the input failed
+    Io(#[cfg_attr(feature = "serde", serde(with = "io_kind"))] std::io::ErrorKind)
+}
+impl Display for TokenizeErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenizeErrorKind::InconsistentIndentation => write!(f, "inconsistent use of tabs and spaces in indentation"),
+            TokenizeErrorKind::UnmatchedDedent => write!(f, "unindent does not match any outer indentation level"),
+            TokenizeErrorKind::InvalidNumber => write!(f, "invalid numeric literal"),
+            TokenizeErrorKind::InvalidNumberSuffix => write!(f, "invalid suffix for numeric literal"),
+            TokenizeErrorKind::NumberOverflow => write!(f, "numeric literal out of range for its type"),
+            TokenizeErrorKind::InvalidEscape => write!(f, "unknown character escape"),
+            TokenizeErrorKind::InvalidHexEscape => write!(f, "hexadecimal escape needs exactly two digits"),
+            TokenizeErrorKind::InvalidUnicodeEscape => write!(f, "invalid unicode escape"),
+            TokenizeErrorKind::LoneSurrogate => write!(f, "unicode escape of an unpaired surrogate"),
+            TokenizeErrorKind::UnterminatedEscape => write!(f, "escape at the end of the input"),
+            TokenizeErrorKind::EmptyCharLiteral => write!(f, "empty char literal"),
+            TokenizeErrorKind::MultiCharLiteral => write!(f, "char literal must hold exactly one character"),
+            TokenizeErrorKind::UnterminatedLiteral => write!(f, "literal is never closed"),
+            TokenizeErrorKind::InvalidEncoding(kind) => write!(f, "{}", kind),
+            TokenizeErrorKind::Io(kind) => write!(f, "failed to read input: {}", kind)
+        }
+    }
+}
+
+/// IO error kinds as their names, such as `"NotFound"`
+#[cfg(feature = "serde")]
+mod io_kind {
+    use std::io::ErrorKind;
+
+    use serde::{Serializer, Deserializer, Deserialize};
+
+    use super::IO_KINDS;
+
+    pub fn serialize<S: Serializer>(kind: &ErrorKind, serializer: S) -> Result<S::Ok, S::Error> {
+        let known = IO_KINDS.iter().find(|known| *known == kind).unwrap_or(&ErrorKind::Other);
+        serializer.serialize_str(&format!("{:?}", known))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ErrorKind, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(IO_KINDS.into_iter().find(|known| format!("{:?}", known) == name).unwrap_or(ErrorKind::Other))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TokenizeError {
+    pub kind: TokenizeErrorKind,
+    pub position: TokenPosition,
+    /// Bytes of the source the error is about
+    pub span: Span
+}
+impl TokenizeError {
+    pub fn new(kind: TokenizeErrorKind, position: TokenPosition, span: Span) -> TokenizeError {
+        TokenizeError {
+            kind,
+            position,
+            span
+        }
+    }
+}
+impl Display for TokenizeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at line {}, position {}", self.kind, self.position.line + 1, self.position.position)
+    }
+}
+impl std::error::Error for TokenizeError {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DecodeErrorKind {
+    /// Bytes that are not valid UTF-8
+    InvalidUtf8,
+    /// A UTF-16 surrogate that is not part of a valid pair
+    UnpairedSurrogate,
+    /// UTF-16 input with an odd number of bytes
+    TruncatedUtf16
+}
+impl Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeErrorKind::InvalidUtf8 => write!(f, "invalid UTF-8"),
+            DecodeErrorKind::UnpairedSurrogate => write!(f, "unpaired UTF-16 surrogate"),
+            DecodeErrorKind::TruncatedUtf16 => write!(f, "UTF-16 input ends in the middle of a code unit")
+        }
+    }
+}
+
+/// Input bytes that could not be turned into text
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DecodeError {
+    pub kind: DecodeErrorKind,
+    /// Offset of the first bad byte in the input
+    pub offset: usize
+}
+impl DecodeError {
+    pub fn new(kind: DecodeErrorKind, offset: usize) -> DecodeError {
+        DecodeError {
+            kind,
+            offset
+        }
+    }
+}
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.kind, self.offset)
+    }
+}
+impl std::error::Error for DecodeError {}
+
+/// Why `binary::encode` or `binary::decode` refused their input
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BinaryError {
+    /// The input does not start with the header of encoded tokens
+    NotTokens,
+    /// The tokens were encoded by a version of the format this one cannot read
+    UnsupportedVersion(u16),
+    /// The checksum in the header does not match the data, which was damaged after encoding
+    ChecksumMismatch { expected: u32, found: u32 },
+    /// The input ends before all of its tokens were read
+    Truncated,
+    /// A byte that does not name any token kind, error or line ending where one was expected
+    InvalidTag(u8),
+    /// A string that is out of the string table or not valid UTF-8, or a number out of range
+    InvalidValue,
+    /// Bytes left over after the last token
+    TrailingBytes,
+    /// A `Symbol` token to encode without the interner it comes from
+    UnknownSymbol(Symbol)
+}
+impl Display for BinaryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BinaryError::NotTokens => write!(f, "not encoded tokens"),
+            BinaryError::UnsupportedVersion(version) => write!(f, "unsupported format version {}", version),
+            BinaryError::ChecksumMismatch { expected, found } => write!(f, "checksum mismatch, expected {:08x} but found {:08x}", expected, found),
+            BinaryError::Truncated => write!(f, "encoded tokens end too early"),
+            BinaryError::InvalidTag(tag) => write!(f, "invalid tag {}", tag),
+            BinaryError::InvalidValue => write!(f, "invalid value in encoded tokens"),
+            BinaryError::TrailingBytes => write!(f, "bytes left after the last token"),
+            BinaryError::UnknownSymbol(symbol) => write!(f, "symbol {} is not in the interner", symbol.index())
+        }
+    }
+}
+impl std::error::Error for BinaryError {}
+
+/// A token a parser did not expect, made by `TokenCursor::expect` and `TokenCursor::unexpected`
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    /// Kinds that would have been accepted, empty when the parser did not say
+    pub expected: Vec<TokenKind>,
+    /// Kind of the token found instead, `None` at the end of the tokens
+    pub found: Option<TokenKind>,
+    pub position: TokenPosition,
+    /// Bytes of the source of the token found, or an empty span after the last token
+    pub span: Span
+}
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.expected.as_slice() {
+            [] => write!(f, "unexpected ")?,
+            [kind] => write!(f, "expected {:?}, found ", kind)?,
+            kinds => {
+                let names: Vec<_> = kinds.iter().map(|kind| format!("{:?}", kind)).collect();
+                write!(f, "expected one of {}, found ", names.join(", "))?;
+            }
+        }
+        match self.found {
+            Some(kind) => write!(f, "{:?}", kind)?,
+            None => write!(f, "end of input")?
+        }
+        write!(f, " at line {}, position {}", self.position.line + 1, self.position.position)
+    }
+}
+impl std::error::Error for ParseError {}
+
+/// Delimiters that do not pair up, found while building token trees
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DelimiterError {
+    /// An opening delimiter never closed, `end` being the empty span where the tokens end
+    Unclosed { delimiter: Delimiter, open_span: Span, end: Span },
+    /// A closing delimiter with no opening one before it
+    Unopened { delimiter: Delimiter, close_span: Span },
+    /// A group closed by a delimiter of another kind
+    Mismatched { open: Delimiter, open_span: Span, close: Delimiter, close_span: Span }
+}
+impl Display for DelimiterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DelimiterError::Unclosed { delimiter, open_span, .. } =>
+                write!(f, "unclosed delimiter `{}` opened at byte {}", delimiter.open_str(), open_span.start),
+            DelimiterError::Unopened { delimiter, close_span } =>
+                write!(f, "unexpected closing delimiter `{}` at byte {}", delimiter.close_str(), close_span.start),
+            DelimiterError::Mismatched { open, open_span, close, close_span } =>
+                write!(f, "mismatched closing delimiter `{}` at byte {} for `{}` opened at byte {}", close.close_str(), close_span.start, open.open_str(), open_span.start)
+        }
+    }
+}
+impl std::error::Error for DelimiterError {}
@@ -0,0 +1,160 @@
+use std::{str::Chars, borrow::Cow};
+
+use crate::{options::Escapes, error::TokenizeErrorKind};
+
+/// Reads one escape sequence, right after its backslash.
+///
+/// Gives the character it stands for, or `None` for line continuations, which stand for nothing.
+/// On malformed sequences the characters read so far stay consumed.
+pub(crate) fn unescape(chars: &mut Chars, escapes: &Escapes) -> Result<Option<char>, TokenizeErrorKind> {
+    let c = chars.next().ok_or(TokenizeErrorKind::UnterminatedEscape)?;
+
+    if let Some((_, value)) = escapes.simple.iter().find(|(escape, _)| *escape == c) {
+        return Ok(Some(*value));
+    }
+    match c {
+        'u' if escapes.unicode => unescape_unicode(chars).map(Some),
+        'x' if escapes.hex => {
+            let value = read_hex(chars, 2).ok_or(TokenizeErrorKind::InvalidHexEscape)?;
+            Ok(char::from_u32(value))
+        },
+        '\n' | '\r' if escapes.line_continuation => {
+            if c == '\r' && chars.as_str().starts_with('\n') {
+                chars.next();
+            }
+            let indentation = chars.as_str().len() - chars.as_str().trim_start_matches([' ', '\t']).len();
+            for _ in 0..indentation {
+                chars.next();
+            }
+            Ok(None)
+        },
+        _ => Err(TokenizeErrorKind::InvalidEscape)
+    }
+}
+
+/// Value of the content of a scope, with its escapes applied and malformed ones replaced by `U+FFFD`
+pub(crate) fn cook<'s>(content: &'s str, escapes: Option<&Escapes>) -> Cow<'s, str> {
+    let Some(escapes) = escapes.filter(|_| content.contains('\\')) else {
+        return Cow::Borrowed(content);
+    };
+
+    let mut cooked = String::with_capacity(content.len());
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            cooked.push(c);
+            continue;
+        }
+        match unescape(&mut chars, escapes) {
+            Ok(value) => cooked.extend(value),
+            Err(_) => cooked.push(char::REPLACEMENT_CHARACTER)
+        }
+    }
+    Cow::Owned(cooked)
+}
+
+fn unescape_unicode(chars: &mut Chars) -> Result<char, TokenizeErrorKind> {
+    if chars.as_str().starts_with('{') {
+        chars.next();
+        let digits = chars.as_str().find('}').ok_or(TokenizeErrorKind::InvalidUnicodeEscape)?;
+        if digits == 0 || digits > 6 {
+            return Err(TokenizeErrorKind::InvalidUnicodeEscape);
+        }
+        let value = read_hex(chars, digits).ok_or(TokenizeErrorKind::InvalidUnicodeEscape)?;
+        chars.next();
+        return code_point(value);
+    }
+
+    let value = read_hex(chars, 4).ok_or(TokenizeErrorKind::InvalidUnicodeEscape)?;
+    match value {
+        0xD800..=0xDBFF => {
+            // A high surrogate must be followed by the escape of its low surrogate
+            let rest = chars.as_str();
+            let low = rest.strip_prefix("\\u")
+                .filter(|low| low.len() >= 4 && low.is_char_boundary(4))
+                .and_then(|low| u32::from_str_radix(&low[..4], 16).ok())
+                .filter(|low| (0xDC00..=0xDFFF).contains(low))
+                .ok_or(TokenizeErrorKind::LoneSurrogate)?;
+            for _ in 0..6 {
+                chars.next();
+            }
+            code_point(0x10000 + ((value - 0xD800) << 10) + (low - 0xDC00))
+        },
+        _ => code_point(value)
+    }
+}
+
+fn code_point(value: u32) -> Result<char, TokenizeErrorKind> {
+    match value {
+        0xD800..=0xDFFF => Err(TokenizeErrorKind::LoneSurrogate),
+        _ => char::from_u32(value).ok_or(TokenizeErrorKind::InvalidUnicodeEscape)
+    }
+}
+
+/// Reads exactly `count` hexadecimal digits
+fn read_hex(chars: &mut Chars, count: usize) -> Option<u32> {
+    let mut value = 0;
+    for _ in 0..count {
+        let digit = chars.clone().next()?.to_digit(16)?;
+        chars.next();
+        value = value * 16 + digit;
+    }
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::default_escapes;
+
+    use super::*;
+
+    fn unescape_all(text: &str) -> Result<String, TokenizeErrorKind> {
+        let escapes = default_escapes();
+        let mut chars = text.chars();
+        let mut output = String::new();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                output.extend(unescape(&mut chars, &escapes)?);
+            } else {
+                output.push(c);
+            }
+        }
+        Ok(output)
+    }
+
+    #[test]
+    fn simple_escapes() {
+        assert_eq!(unescape_all(r#"a\nb\tc\r\0\\\"\'"#), Ok("a\nb\tc\r\0\\\"'".to_string()));
+    }
+
+    #[test]
+    fn unicode_and_hex_escapes() {
+        assert_eq!(unescape_all(r"\u{1F600}\u00e9\x41"), Ok("😀éA".to_string()));
+        assert_eq!(unescape_all(r"\uD83D\uDE00"), Ok("😀".to_string()));
+    }
+
+    #[test]
+    fn line_continuation() {
+        assert_eq!(unescape_all("one \\\n    two"), Ok("one two".to_string()));
+        assert_eq!(unescape_all("one \\\r\n\ttwo"), Ok("one two".to_string()));
+    }
+
+    #[test]
+    fn invalid_escapes() {
+        assert_eq!(unescape_all(r"\q"), Err(TokenizeErrorKind::InvalidEscape));
+        assert_eq!(unescape_all(r"\x4"), Err(TokenizeErrorKind::InvalidHexEscape));
+        assert_eq!(unescape_all(r"\u{}"), Err(TokenizeErrorKind::InvalidUnicodeEscape));
+        assert_eq!(unescape_all(r"\u{1234567}"), Err(TokenizeErrorKind::InvalidUnicodeEscape));
+        assert_eq!(unescape_all(r"\u{110000}"), Err(TokenizeErrorKind::InvalidUnicodeEscape));
+        assert_eq!(unescape_all(r"\u{D800}"), Err(TokenizeErrorKind::LoneSurrogate));
+        assert_eq!(unescape_all(r"\uD83Dx"), Err(TokenizeErrorKind::LoneSurrogate));
+        assert_eq!(unescape_all(r"\uDE00"), Err(TokenizeErrorKind::LoneSurrogate));
+        assert_eq!(unescape_all("\\"), Err(TokenizeErrorKind::UnterminatedEscape));
+    }
+
+    #[test]
+    fn disabled_escapes_are_invalid() {
+        let escapes = Escapes { unicode: false, ..default_escapes() };
+        assert_eq!(unescape(&mut "u{41}".chars(), &escapes), Err(TokenizeErrorKind::InvalidEscape));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{detokenizer::{PrintOptions, Printer}, options::{TokenizerOptions, Scope}, tokenizer::tokenize};
+
+/// The print options used by `format` when nothing else is configured
+pub fn default() -> PrintOptions {
+    PrintOptions {
+        max_width: Some(100),
+        max_blank_lines: Some(1),
+        ..PrintOptions::default()
+    }
+}
+
+/// Formats source code, reindenting blocks and normalizing the spacing between tokens.
+///
+/// Comments are copied exactly as written, and the output always ends with a single newline.
+pub fn format(source: &str, options: &TokenizerOptions, print: &PrintOptions) -> String {
+    let mut printer = Printer::new(print);
+    for segment in split_comments(source, &options.scopes) {
+        match segment {
+            Segment::Code(code) => {
+                for token in tokenize(code.to_string(), options) {
+                    printer.push(&token);
+                }
+            },
+            Segment::LineComment(comment) => printer.push_comment(comment, true),
+            Segment::BlockComment(comment) => printer.push_comment(comment, false)
+        }
+    }
+
+    let ending = printer.line_ending();
+    let mut formatted = printer.finish();
+    let content_end = formatted.trim_end().len();
+    formatted.truncate(content_end);
+    let formatted = formatted.trim_start_matches(['\n', '\r', '\u{2028}', '\u{2029}']);
+    if formatted.is_empty() {
+        String::new()
+    } else {
+        format!("{}{}", formatted, ending.as_str())
+    }
+}
+
+/// Checks whether `format` would leave the source untouched
+pub fn is_formatted(source: &str, options: &TokenizerOptions, print: &PrintOptions) -> bool {
+    format(source, options, print) == source
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment<'s> {
+    Code(&'s str),
+    LineComment(&'s str),
+    BlockComment(&'s str)
+}
+
+fn split_comments<'s>(source: &'s str, scopes: &[Scope]) -> Vec<Segment<'s>> {
+    let mut segments = vec![];
+    let mut code_start = 0;
+    let mut open_scope: Option<&Scope> = None;
+
+    let mut chars = source.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        if let Some(scope) = open_scope {
+            if c == '\\' && scope.escapes.is_some() {
+                chars.next();
+            } else if c == scope.end {
+                open_scope = None;
+            }
+            continue;
+        }
+        if let Some(scope) = scopes.iter().find(|scope| scope.begin == c) {
+            open_scope = Some(scope);
+            continue;
+        }
+
+        let rest = &source[index..];
+        let comment = if rest.starts_with("//") {
+            Some(Segment::LineComment(&rest[..rest.find(['\n', '\r', '\u{2028}', '\u{2029}']).unwrap_or(rest.len())]))
+        } else {
+            rest.strip_prefix("/*")
+                .map(|body| Segment::BlockComment(&rest[..body.find("*/").map(|end| end + 4).unwrap_or(rest.len())]))
+        };
+        if let Some(comment) = comment {
+            if code_start < index {
+                segments.push(Segment::Code(&source[code_start..index]));
+            }
+            let comment_end = match comment {
+                Segment::LineComment(text) | Segment::BlockComment(text) | Segment::Code(text) => text.len()
+            };
+            segments.push(comment);
+
+            code_start = index + comment_end;
+            while chars.peek().is_some_and(|(next, _)| *next < code_start) {
+                chars.next();
+            }
+        }
+    }
+    if code_start < source.len() {
+        segments.push(Segment::Code(&source[code_start..]));
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::LineEnding;
+
+    use super::*;
+
+    fn format_default(source: &str) -> String {
+        format(source, &crate::options::default(), &default())
+    }
+
+    #[test]
+    fn format_reindents_blocks() {
+        let source = "fn main() {\nif (a) {\n  b(1,2);\n      }\n}\n";
+        assert_eq!(format_default(source), "fn main() {\n    if (a) {\n        b(1, 2);\n    }\n}\n");
+    }
+
+    #[test]
+    fn format_normalizes_operator_spacing() {
+        assert_eq!(format_default("x=a+b*c;"), "x = a + b * c;\n");
+    }
+
+    #[test]
+    fn format_keeps_subtraction_apart_from_negative_numbers() {
+        assert_eq!(format_default("x = a - 1;\n"), "x = a - 1;\n");
+        assert_eq!(format_default("x = a-1;\n"), "x = a - 1;\n");
+        assert_eq!(format_default("x = f(-1) - -2;\n"), "x = f(-1) - -2;\n");
+    }
+
+    #[test]
+    fn format_preserves_comments() {
+        let source = "x = 1; // don't   touch,this\n/* keep\n   me */ y = 2;\n";
+        assert_eq!(format_default(source), "x = 1; // don't   touch,this\n/* keep\n   me */ y = 2;\n");
+    }
+
+    #[test]
+    fn format_ignores_comment_markers_inside_strings() {
+        assert_eq!(format_default("url = \"http://x\";\n"), "url = \"http://x\";\n");
+    }
+
+    #[test]
+    fn format_wraps_long_lines_at_commas() {
+        let print = PrintOptions { max_width: Some(20), ..default() };
+        let formatted = format("call(alpha, beta, gamma, delta);", &crate::options::default(), &print);
+        assert_eq!(formatted, "call(alpha, beta,\n    gamma, delta);\n");
+    }
+
+    #[test]
+    fn format_collapses_blank_lines() {
+        assert_eq!(format_default("a;\n\n\n\nb;\n\n"), "a;\n\nb;\n");
+    }
+
+    #[test]
+    fn format_is_idempotent() {
+        let source = "fn f(a,b){\n// note\nreturn a+b;}\n\n\n\nfn g(){ call(one, two, three, four, five, six, seven, eight, nine, ten, eleven, twelve, thirteen); }";
+        let once = format_default(source);
+        assert_eq!(format_default(&once), once);
+        assert!(is_formatted(&once, &crate::options::default(), &default()));
+    }
+
+    #[test]
+    fn format_keeps_or_normalizes_line_endings() {
+        let source = "a;\r\n// note\r\nb;\r\n";
+        assert_eq!(format_default(source), source);
+
+        let print = PrintOptions { line_ending: Some(LineEnding::Lf), ..default() };
+        assert_eq!(format(source, &crate::options::default(), &print), "a;\n// note\nb;\n");
+    }
+}
@@ -0,0 +1,215 @@
+use std::ops::Range;
+
+use crate::{options::TokenizerOptions, token::{Token, TokenType, Span}, stream::BoundaryScanner, tokenizer::{tokenize, tokenize_piece, end_of_input}};
+
+/// Tokens an edit replaced, and the ones that replaced them
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TokenChange {
+    /// Range in the token list before the edit
+    pub old: Range<usize>,
+    /// Range in the token list after the edit
+    pub new: Range<usize>
+}
+
+/// Keeps the tokens of a text up to date as the text is edited.
+///
+/// Each edit is tokenized again from the last line break before it, and only until the tokens line up
+/// with the previous ones again. With indentation tracking enabled, every edit tokenizes the whole text.
+pub struct IncrementalTokenizer<'o> {
+    options: &'o TokenizerOptions,
+    text: String,
+    tokens: Vec<Token>
+}
+impl<'o> IncrementalTokenizer<'o> {
+    pub fn new(text: String, options: &'o TokenizerOptions) -> Self {
+        let tokens = tokenize(text.clone(), options);
+        IncrementalTokenizer {
+            options,
+            text,
+            tokens
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// Replaces the bytes of `span` by `replacement`, giving the tokens that changed.
+    ///
+    /// Panics if `span` does not lie on character boundaries of the text, as `String::replace_range` does.
+    pub fn edit(&mut self, span: Span, replacement: &str) -> TokenChange {
+        let old_line_endings = self.options.line_ending_policy().resolve(&self.text);
+        self.text.replace_range(span.start..span.end, replacement);
+        let line_endings = self.options.line_ending_policy().resolve(&self.text);
+
+        if self.options.indentation.is_some() || line_endings != old_line_endings {
+            let tokens = tokenize(self.text.clone(), self.options);
+            let old = std::mem::replace(&mut self.tokens, tokens);
+            return trim_change(&old, &self.tokens, 0..old.len(), 0..self.tokens.len());
+        }
+
+        // Restart after the last line break that ends before the edit, everything up to it is unchanged
+        let before = self.tokens.partition_point(|token| token.1.span.end < span.start);
+        let (start, offset, line) = match self.tokens[..before].iter().rposition(is_line_break) {
+            Some(index) => (index + 1, self.tokens[index].1.span.end, self.tokens[index].1.position.line as usize + 1),
+            None => (0, 0, 0)
+        };
+        let delta = replacement.len() as isize - span.len() as isize;
+        let edit_end = span.start + replacement.len();
+
+        let mut scanner = BoundaryScanner::new();
+        scanner.scanned = offset;
+        let mut relexed = vec![];
+        let (mut chunk_start, mut chunk_line) = (offset, line);
+        let tail = loop {
+            let Some(end) = scanner.next(&self.text, &self.options.scopes, line_endings) else {
+                let rest = &self.text[chunk_start..];
+                relexed.extend(tokenize_piece(rest, line_endings, self.options, chunk_start, chunk_line));
+                if self.options.end_of_input {
+                    relexed.push(end_of_input(rest, line_endings, chunk_line, chunk_start));
+                }
+                break None;
+            };
+            let chunk = &self.text[chunk_start..end];
+            relexed.extend(tokenize_piece(chunk, line_endings, self.options, chunk_start, chunk_line));
+            chunk_line += line_endings.lines(chunk).len() - 1;
+            chunk_start = end;
+
+            // Past the edit, a line break where the old text had one means the rest is the same as before
+            if end >= edit_end {
+                let old_end = (end as isize - delta) as usize;
+                let index = self.tokens.partition_point(|token| token.1.span.end < old_end);
+                if let Some(token) = self.tokens.get(index).filter(|token| is_line_break(token) && token.1.span.end == old_end) {
+                    let line_delta = chunk_line as isize - (token.1.position.line as isize + 1);
+                    break Some((index + 1, line_delta));
+                }
+            }
+        };
+
+        let end = match tail {
+            Some((end, line_delta)) => {
+                for token in &mut self.tokens[end..] {
+                    shift(token, delta, line_delta);
+                }
+                end
+            },
+            None => self.tokens.len()
+        };
+        let inserted = relexed.len();
+        let old: Vec<Token> = self.tokens.splice(start..end, relexed).collect();
+        trim_change(&old, &self.tokens[start..start + inserted], start..end, start..start + inserted)
+    }
+}
+
+fn is_line_break(token: &Token) -> bool {
+    matches!(token.0, TokenType::Newline(_) | TokenType::EOF)
+}
+
+fn shift(token: &mut Token, delta: isize, line_delta: isize) {
+    let span = token.1.span;
+    token.1.span = Span::new((span.start as isize + delta) as usize, (span.end as isize + delta) as usize);
+    token.1.position.line += line_delta as i32;
+}
+
+/// Narrows a change to the tokens that really differ, given the `old` tokens of `old_range` and the `new` tokens of `new_range`
+fn trim_change(old: &[Token], new: &[Token], old_range: Range<usize>, new_range: Range<usize>) -> TokenChange {
+    let old = &old[old.len() - old_range.len()..];
+    let new = &new[new.len() - new_range.len()..];
+    let prefix = old.iter().zip(new).take_while(|(old, new)| old == new).count();
+    TokenChange {
+        old: old_range.start + prefix..old_range.end,
+        new: new_range.start + prefix..new_range.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::{self, LineEndings, Indentation};
+
+    use super::*;
+
+    /// xorshift64, enough to pick edits reproducibly
+    struct Rng(u64);
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: usize) -> usize {
+            (self.next() % bound as u64) as usize
+        }
+    }
+
+    const PIECES: [&str; 22] = [
+        "a", "bc", "if", " ", "\t", "\n", "\n", "\r\n", "\r", "\"", "'", "\\", "\\n", "1", "0x1F", "2.5", ".", "-", "=", "(", ")", "é中"
+    ];
+
+    fn random_text(rng: &mut Rng, pieces: usize) -> String {
+        (0..rng.below(pieces)).map(|_| PIECES[rng.below(PIECES.len())]).collect()
+    }
+
+    fn random_boundary(rng: &mut Rng, text: &str) -> usize {
+        let mut offset = rng.below(text.len() + 1);
+        while !text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+
+    fn check_random_edits(options: &TokenizerOptions, seed: u64) {
+        let mut rng = Rng(seed);
+        for _ in 0..300 {
+            let text = random_text(&mut rng, 40);
+            let mut incremental = IncrementalTokenizer::new(text, options);
+            for _ in 0..5 {
+                let (a, b) = (random_boundary(&mut rng, incremental.text()), random_boundary(&mut rng, incremental.text()));
+                let span = Span::new(a.min(b), a.max(b));
+                let replacement = random_text(&mut rng, 4);
+                let before = incremental.tokens().to_vec();
+                let change = incremental.edit(span, &replacement);
+
+                let expected = tokenize(incremental.text().to_string(), options);
+                assert_eq!(incremental.tokens(), expected, "after replacing {:?} by {:?}", span, replacement);
+                assert_eq!(before[..change.old.start], expected[..change.new.start]);
+                assert_eq!(before.len() - change.old.end, expected.len() - change.new.end);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_full_tokenization_after_random_edits() {
+        check_random_edits(&options::default(), 0x2545F4914F6CDD1D);
+
+        let mut auto = options::default();
+        auto.line_endings = LineEndings::Auto;
+        check_random_edits(&auto, 0x9E3779B97F4A7C15);
+
+        check_random_edits(&options::legacy(), 0xD1B54A32D192ED03);
+
+        let mut indented = options::default();
+        indented.indentation = Some(Indentation::new(4));
+        check_random_edits(&indented, 0x94D049BB133111EB);
+    }
+
+    #[test]
+    fn only_retokenizes_the_edited_line() {
+        let options = options::default();
+        let mut incremental = IncrementalTokenizer::new("a = 1\nb = 2\nc = 3\n".to_string(), &options);
+        let change = incremental.edit(Span::new(10, 11), "20");
+        assert_eq!(change, TokenChange { old: 6..8, new: 6..8 });
+        assert_eq!(incremental.tokens()[6].0, TokenType::IntegerLiteral(20));
+        assert_eq!(incremental.tokens()[11].1.span, Span::new(18, 19));
+
+        // Opening a string changes everything after it
+        let change = incremental.edit(Span::new(0, 0), "\"");
+        assert_eq!(change, TokenChange { old: 0..13, new: 0..2 });
+        assert_eq!(incremental.tokens()[1].0, TokenType::EndOfInput);
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{options::{Indentation, LineEndings, LineEnding}, token::{Token, TokenType, TokenData, TokenPosition, Span}, error::TokenizeErrorKind};
+
+/// The leading whitespace of a source line
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct LineIndent {
+    pub text: String,
+    /// Byte offset of the line in the source
+    pub start: usize,
+    /// Width with tabs expanded to the configured tab width
+    pub column: usize,
+    /// Width with tabs counted as a single column, used to detect tab/space mixing
+    pub alt_column: usize
+}
+
+pub(crate) fn measure_lines(text: &str, indentation: &Indentation, line_endings: LineEndings) -> Vec<LineIndent> {
+    line_endings.lines(text).into_iter()
+        .map(|(start, end)| measure_line(&text[start..end], start, indentation.tab_width))
+        .collect()
+}
+
+fn measure_line(line: &str, start: usize, tab_width: usize) -> LineIndent {
+    let mut column = 0;
+    let mut alt_column = 0;
+    let mut len = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => column += 1,
+            '\t' => column = (column / tab_width.max(1) + 1) * tab_width.max(1),
+            _ => break
+        }
+        alt_column += 1;
+        len += c.len_utf8();
+    }
+    LineIndent {
+        text: line[..len].to_string(),
+        start,
+        column,
+        alt_column
+    }
+}
+
+/// Replaces the newlines of a token stream by `Newline`, `Indent` and `Dedent` tokens, as Python does.
+///
+/// Newlines of empty lines and of lines inside open brackets are dropped.
+pub(crate) fn apply_indentation(tokens: Vec<Token>, lines: &[LineIndent]) -> Vec<Token> {
+    let mut output = Vec::with_capacity(tokens.len());
+    let mut indenter = Indenter::new();
+    for token in tokens {
+        indenter.push(token, lines, 0, &mut output);
+    }
+    indenter.finish(&mut output);
+    output
+}
+
+/// State of `apply_indentation` between tokens, so a stream can be indented a piece at a time
+pub(crate) struct Indenter {
+    stack: Vec<(usize, usize)>,
+    bracket_depth: usize,
+    line: usize,
+    at_line_start: bool,
+    has_content: bool,
+    ending: LineEnding,
+    /// Position and end of the last token written
+    end: (TokenPosition, usize)
+}
+impl Indenter {
+    pub(crate) fn new() -> Self {
+        Indenter {
+            stack: vec![(0, 0)],
+            bracket_depth: 0,
+            line: 0,
+            at_line_start: true,
+            has_content: false,
+            ending: LineEnding::Lf,
+            end: (TokenPosition::default(), 0)
+        }
+    }
+
+    /// Indents one token, with `lines` holding the indentation of the lines from `first_line` on
+    pub(crate) fn push(&mut self, token: Token, lines: &[LineIndent], first_line: usize, output: &mut Vec<Token>) {
+        let line_break = match &token.0 {
+            TokenType::Newline(ending) => Some(*ending),
+            TokenType::EOF => LineEnding::at(&token.1.text),
+            _ => None
+        };
+        if let Some(line_break) = line_break {
+            self.ending = line_break;
+            self.line = token.1.position.line as usize + 1;
+            if self.bracket_depth == 0 {
+                if self.has_content {
+                    self.write(Token(TokenType::Newline(line_break), token.1), output);
+                    self.has_content = false;
+                }
+                self.at_line_start = true;
+            }
+            return;
+        }
+
+        if self.at_line_start {
+            let position = TokenPosition::new(0, self.line as i32);
+            let empty = LineIndent { text: String::new(), start: 0, column: 0, alt_column: 0 };
+            let indent = self.line.checked_sub(first_line).and_then(|line| lines.get(line)).unwrap_or(&empty);
+            indent_line(indent, position, &mut self.stack, output);
+            self.at_line_start = false;
+        }
+
+        match token.0 {
+            TokenType::OpenParenthesis | TokenType::OpenBracket | TokenType::OpenCurlyBrace => self.bracket_depth += 1,
+            TokenType::CloseParenthesis | TokenType::CloseBracket | TokenType::CloseCurlyBrace => self.bracket_depth = self.bracket_depth.saturating_sub(1),
+            _ => {}
+        }
+        self.has_content = true;
+        self.write(token, output);
+    }
+
+    /// Ends the last logical line and closes the blocks still open
+    pub(crate) fn finish(self, output: &mut Vec<Token>) {
+        let (end, end_offset) = self.end;
+        let end_token = |kind| {
+            let mut data = TokenData::new(String::new(), end);
+            data.span = Span::new(end_offset, end_offset);
+            Token(kind, data)
+        };
+        if self.has_content {
+            output.push(end_token(TokenType::Newline(self.ending)));
+        }
+        for _ in 1..self.stack.len() {
+            output.push(end_token(TokenType::Dedent));
+        }
+    }
+
+    fn write(&mut self, token: Token, output: &mut Vec<Token>) {
+        self.end = (token.1.position, token.1.span.end);
+        output.push(token);
+    }
+}
+
+fn indent_line(indent: &LineIndent, position: TokenPosition, stack: &mut Vec<(usize, usize)>, output: &mut Vec<Token>) {
+    let token = |kind| {
+        let mut data = TokenData::new(indent.text.clone(), position);
+        data.span = Span::new(indent.start, indent.start + indent.text.len());
+        Token(kind, data)
+    };
+    let dedent = || {
+        let mut data = TokenData::new(String::new(), position);
+        data.span = Span::new(indent.start, indent.start);
+        Token(TokenType::Dedent, data)
+    };
+    let error = |kind| token(TokenType::Error(kind));
+    let (column, alt_column) = (indent.column, indent.alt_column);
+
+    let &(top, alt_top) = stack.last().unwrap();
+    if column > top {
+        if alt_column <= alt_top {
+            output.push(error(TokenizeErrorKind::InconsistentIndentation));
+        }
+        stack.push((column, alt_column));
+        output.push(token(TokenType::Indent));
+    } else if column == top {
+        if alt_column != alt_top {
+            output.push(error(TokenizeErrorKind::InconsistentIndentation));
+        }
+    } else {
+        while stack.len() > 1 && column < stack.last().unwrap().0 {
+            stack.pop();
+            output.push(dedent());
+        }
+        let &(top, alt_top) = stack.last().unwrap();
+        if column != top {
+            output.push(error(TokenizeErrorKind::UnmatchedDedent));
+            stack.push((column, alt_column));
+        } else if alt_column != alt_top {
+            output.push(error(TokenizeErrorKind::InconsistentIndentation));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{options::{self, Indentation}, tokenizer::{tokenize, try_tokenize}, error::TokenizeErrorKind};
+
+    use super::*;
+
+    const NEWLINE: TokenType = TokenType::Newline(LineEnding::Lf);
+
+    fn kinds(text: &str) -> Vec<TokenType> {
+        let mut options = options::default();
+        options.indentation = Some(Indentation::new(8));
+        options.end_of_input = false;
+        tokenize(text.to_string(), &options).into_iter().map(|token| token.0).collect()
+    }
+
+    fn id(name: &str) -> TokenType {
+        TokenType::Id(name.to_string())
+    }
+
+    #[test]
+    fn emits_indent_and_dedent() {
+        assert_eq!(kinds("if a:\n    b\n    c\nd\n"), vec![
+            id("if"), id("a"), TokenType::Colon, NEWLINE,
+            TokenType::Indent, id("b"), NEWLINE,
+            id("c"), NEWLINE,
+            TokenType::Dedent, id("d"), NEWLINE
+        ]);
+    }
+
+    #[test]
+    fn closes_open_blocks_at_the_end() {
+        assert_eq!(kinds("a:\n  b:\n    c"), vec![
+            id("a"), TokenType::Colon, NEWLINE,
+            TokenType::Indent, id("b"), TokenType::Colon, NEWLINE,
+            TokenType::Indent, id("c"), NEWLINE,
+            TokenType::Dedent, TokenType::Dedent
+        ]);
+    }
+
+    #[test]
+    fn skips_blank_lines() {
+        assert_eq!(kinds("a\n\n   \n  b\n"), vec![
+            id("a"), NEWLINE, TokenType::Indent, id("b"), NEWLINE, TokenType::Dedent
+        ]);
+    }
+
+    #[test]
+    fn suppressed_inside_brackets() {
+        assert_eq!(kinds("f(a,\n        b)\nc\n"), vec![
+            id("f"), TokenType::OpenParenthesis, id("a"), TokenType::Comma, id("b"), TokenType::CloseParenthesis, NEWLINE,
+            id("c"), NEWLINE
+        ]);
+    }
+
+    #[test]
+    fn uses_tab_width() {
+        assert_eq!(kinds("a\n\tb\n        c\n"), vec![
+            id("a"), NEWLINE, TokenType::Indent, id("b"), NEWLINE, TokenType::Error(TokenizeErrorKind::InconsistentIndentation), id("c"), NEWLINE, TokenType::Dedent
+        ]);
+    }
+
+    #[test]
+    fn detects_unmatched_dedent() {
+        let mut options = options::default();
+        options.indentation = Some(Indentation::new(4));
+        let err = try_tokenize("a\n    b\n  c\n".to_string(), &options).unwrap_err();
+        assert_eq!(err.kind, TokenizeErrorKind::UnmatchedDedent);
+        assert_eq!(err.position.line, 2);
+    }
+
+    #[test]
+    fn consistent_tabs_are_accepted() {
+        let mut options = options::default();
+        options.indentation = Some(Indentation::new(4));
+        assert!(try_tokenize("a\n\tb\n\t\tc\n\td\n".to_string(), &options).is_ok());
+    }
+}
@@ -0,0 +1,158 @@
+use std::{collections::HashMap, sync::{Arc, RwLock}};
+
+/// Handle of a string kept by an interner, two symbols of the same interner are equal when their strings are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct Symbol(u32);
+impl Symbol {
+    /// Position of the symbol in the order strings were first interned
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Keeps one copy of every string given to it, handing out a `Symbol` for each
+#[derive(Debug, Default, Clone)]
+pub struct Interner {
+    symbols: HashMap<Arc<str>, Symbol>,
+    strings: Vec<Arc<str>>
+}
+impl Interner {
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    /// Gives the symbol of `text`, keeping a copy of it the first time it is seen
+    pub fn intern(&mut self, text: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(text) {
+            return *symbol;
+        }
+        let symbol = Symbol(u32::try_from(self.strings.len()).expect("more than u32::MAX interned strings"));
+        let text: Arc<str> = Arc::from(text);
+        self.strings.push(text.clone());
+        self.symbols.insert(text, symbol);
+        symbol
+    }
+
+    /// Gives the symbol of `text` if it was interned already
+    pub fn get(&self, text: &str) -> Option<Symbol> {
+        self.symbols.get(text).copied()
+    }
+
+    /// Gives the string of a symbol of this interner.
+    ///
+    /// Panics if the symbol comes from another interner holding more strings.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.index()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// The interned strings, in the order of their symbols
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &str)> {
+        self.strings.iter().enumerate().map(|(index, text)| (Symbol(index as u32), text.as_ref()))
+    }
+}
+
+/// An `Interner` that many threads can intern into at once
+#[derive(Debug, Default)]
+pub struct SharedInterner {
+    inner: RwLock<Interner>
+}
+impl SharedInterner {
+    pub fn new() -> Self {
+        SharedInterner::default()
+    }
+
+    /// Gives the symbol of `text`, keeping a copy of it the first time it is seen
+    pub fn intern(&self, text: &str) -> Symbol {
+        if let Some(symbol) = self.get(text) {
+            return symbol;
+        }
+        self.inner.write().unwrap().intern(text)
+    }
+
+    /// Gives the symbol of `text` if it was interned already
+    pub fn get(&self, text: &str) -> Option<Symbol> {
+        self.inner.read().unwrap().get(text)
+    }
+
+    /// Gives the string of a symbol of this interner.
+    ///
+    /// Panics if the symbol comes from another interner holding more strings.
+    pub fn resolve(&self, symbol: Symbol) -> Arc<str> {
+        self.inner.read().unwrap().strings[symbol.index()].clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn into_inner(self) -> Interner {
+        self.inner.into_inner().unwrap()
+    }
+}
+impl From<Interner> for SharedInterner {
+    fn from(interner: Interner) -> Self {
+        SharedInterner {
+            inner: RwLock::new(interner)
+        }
+    }
+}
+
+/// Something strings can be interned into, so `tokenize_interned` takes either kind of interner
+pub trait Intern {
+    fn intern(&mut self, text: &str) -> Symbol;
+}
+impl Intern for &mut Interner {
+    fn intern(&mut self, text: &str) -> Symbol {
+        Interner::intern(self, text)
+    }
+}
+impl Intern for &SharedInterner {
+    fn intern(&mut self, text: &str) -> Symbol {
+        SharedInterner::intern(self, text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn interns_each_string_once() {
+        let mut interner = Interner::new();
+        let (a, b) = (interner.intern("alpha"), interner.intern("beta"));
+        assert_ne!(a, b);
+        assert_eq!(interner.intern("alpha"), a);
+        assert_eq!(interner.resolve(b), "beta");
+        assert_eq!(interner.get("gamma"), None);
+        assert_eq!(interner.iter().collect::<Vec<_>>(), vec![(a, "alpha"), (b, "beta")]);
+    }
+
+    #[test]
+    fn shared_interner_agrees_across_threads() {
+        let interner = SharedInterner::new();
+        let symbols: Vec<Vec<Symbol>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| (0..100).map(|i| interner.intern(&format!("name{}", i % 10))).collect()))
+                .collect();
+            workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+        });
+        assert_eq!(interner.len(), 10);
+        assert!(symbols.windows(2).all(|pair| pair[0] == pair[1]));
+        assert_eq!(&*interner.resolve(symbols[0][3]), "name3");
+    }
+}
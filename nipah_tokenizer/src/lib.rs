@@ -1,4 +1,26 @@
 pub mod options;
 pub mod token;
 pub mod tokenizer;
-mod split;
\ No newline at end of file
+pub mod detokenizer;
+pub mod format;
+pub mod error;
+pub mod number;
+pub mod decimal;
+pub mod line_index;
+pub mod encoding;
+pub mod stream;
+pub mod incremental;
+pub mod parallel;
+pub mod interner;
+pub mod token_buffer;
+pub mod binary;
+pub mod cursor;
+pub mod token_tree;
+#[cfg(feature = "async")]
+pub mod token_stream;
+mod split;
+mod indentation;
+mod escape;
+mod scan;
+
+pub use split::Piece;
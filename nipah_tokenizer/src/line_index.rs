@@ -0,0 +1,197 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{token::{Token, Span}, options::LineEndings};
+
+/// What a column counts
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ColumnUnit {
+    Bytes,
+    Chars,
+    /// UTF-16 code units, as used by the Language Server Protocol
+    Utf16,
+    /// User perceived characters
+    Graphemes,
+    /// Terminal cells, with wide characters taking two and tabs reaching the next tab stop
+    Display
+}
+
+/// Zero based line and column
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize
+}
+impl LineCol {
+    pub fn new(line: usize, column: usize) -> LineCol {
+        LineCol {
+            line,
+            column
+        }
+    }
+}
+
+/// Maps byte offsets of a source to lines and columns.
+///
+/// Lines end wherever the tokenizer's `LineEndings` would end them, counting `\r\n` as a single break.
+pub struct LineIndex<'s> {
+    source: &'s str,
+    /// Bytes of each line, without its line break
+    lines: Vec<Span>,
+    /// Lines with only ASCII text, where most units are the same as bytes
+    ascii_lines: Vec<bool>,
+    tab_width: usize
+}
+impl<'s> LineIndex<'s> {
+    /// Indexes a source breaking lines at any of LF, CRLF, CR, U+2028 and U+2029
+    pub fn new(source: &'s str, tab_width: usize) -> LineIndex<'s> {
+        LineIndex::with_line_endings(source, tab_width, LineEndings::Any)
+    }
+
+    pub fn with_line_endings(source: &'s str, tab_width: usize, line_endings: LineEndings) -> LineIndex<'s> {
+        let lines: Vec<Span> = line_endings.lines(source).into_iter()
+            .map(|(start, end)| Span::new(start, end))
+            .collect();
+        let ascii_lines = lines.iter()
+            .map(|line| source[line.start..line.end].is_ascii())
+            .collect();
+        LineIndex {
+            source,
+            lines,
+            ascii_lines,
+            tab_width: tab_width.max(1)
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Bytes of a line, without its line break
+    pub fn line_span(&self, line: usize) -> Option<Span> {
+        self.lines.get(line).copied()
+    }
+
+    /// Line and column of a byte offset, offsets past the end are clamped to it
+    pub fn line_col(&self, offset: usize, unit: ColumnUnit) -> LineCol {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.lines.partition_point(|line| line.start <= offset) - 1;
+        // Offsets inside a line break count as the end of their line
+        let offset = offset.min(self.lines[line].end);
+        LineCol::new(line, self.measure(&self.source[self.lines[line].start..offset], line, unit))
+    }
+
+    /// Line and column where a token starts
+    pub fn token_start(&self, token: &Token, unit: ColumnUnit) -> LineCol {
+        self.line_col(token.1.span.start, unit)
+    }
+
+    /// Line and column where a token ends
+    pub fn token_end(&self, token: &Token, unit: ColumnUnit) -> LineCol {
+        self.line_col(token.1.span.end, unit)
+    }
+
+    /// Byte offset of a line and column, the reverse of `line_col`
+    pub fn offset(&self, position: LineCol, unit: ColumnUnit) -> Option<usize> {
+        let span = self.line_span(position.line)?;
+        let text = &self.source[span.start..span.end];
+        if unit == ColumnUnit::Bytes || (self.ascii_lines[position.line] && unit != ColumnUnit::Display) {
+            return (position.column <= text.len()).then_some(span.start + position.column);
+        }
+
+        let mut column = 0;
+        for (index, grapheme) in text.grapheme_indices(true) {
+            if column >= position.column {
+                return (column == position.column).then_some(span.start + index);
+            }
+            if unit == ColumnUnit::Graphemes || unit == ColumnUnit::Display {
+                column = self.advance(column, grapheme, unit);
+            } else {
+                for (char_index, c) in grapheme.char_indices() {
+                    if column == position.column {
+                        return Some(span.start + index + char_index);
+                    }
+                    column += if unit == ColumnUnit::Utf16 { c.len_utf16() } else { 1 };
+                }
+            }
+        }
+        (column == position.column).then_some(span.end)
+    }
+
+    fn measure(&self, text: &str, line: usize, unit: ColumnUnit) -> usize {
+        if unit == ColumnUnit::Bytes || (self.ascii_lines[line] && unit != ColumnUnit::Display) {
+            return text.len();
+        }
+        match unit {
+            ColumnUnit::Chars => text.chars().count(),
+            ColumnUnit::Utf16 => text.chars().map(char::len_utf16).sum(),
+            _ => text.graphemes(true).fold(0, |column, grapheme| self.advance(column, grapheme, unit))
+        }
+    }
+
+    /// Column after a grapheme, for the units that count graphemes
+    fn advance(&self, column: usize, grapheme: &str, unit: ColumnUnit) -> usize {
+        match (unit, grapheme) {
+            (ColumnUnit::Display, "\t") => (column / self.tab_width + 1) * self.tab_width,
+            (ColumnUnit::Display, _) => column + grapheme.width(),
+            _ => column + 1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tokenizer::tokenize;
+
+    use super::*;
+
+    #[test]
+    fn counts_lines_once_per_break() {
+        let index = LineIndex::new("a\nb\r\nc\rd", 4);
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.line_col(3, ColumnUnit::Bytes), LineCol::new(1, 1));
+        assert_eq!(index.line_col(5, ColumnUnit::Bytes), LineCol::new(2, 0));
+        assert_eq!(index.line_col(7, ColumnUnit::Bytes), LineCol::new(3, 0));
+        assert_eq!(index.line_span(1), Some(Span::new(2, 3)));
+    }
+
+    #[test]
+    fn columns_in_every_unit() {
+        // "é" as e + combining accent, then a wide CJK character and an emoji outside the BMP
+        let source = "x\te\u{301}中😀y";
+        let index = LineIndex::new(source, 4);
+        let y = source.find('y').unwrap();
+        assert_eq!(index.line_col(y, ColumnUnit::Bytes).column, 12);
+        assert_eq!(index.line_col(y, ColumnUnit::Chars).column, 6);
+        assert_eq!(index.line_col(y, ColumnUnit::Utf16).column, 7);
+        assert_eq!(index.line_col(y, ColumnUnit::Graphemes).column, 5);
+        assert_eq!(index.line_col(y, ColumnUnit::Display).column, 9);
+    }
+
+    #[test]
+    fn offsets_round_trip() {
+        let source = "first\r\n\tsecond 中😀 line\nthird";
+        let index = LineIndex::new(source, 8);
+        for unit in [ColumnUnit::Bytes, ColumnUnit::Chars, ColumnUnit::Utf16, ColumnUnit::Graphemes, ColumnUnit::Display] {
+            // The middle of a "\r\n" has no column of its own
+            for (offset, _) in source.char_indices().filter(|(offset, _)| !source[..*offset].ends_with('\r')) {
+                let position = index.line_col(offset, unit);
+                assert_eq!(index.offset(position, unit), Some(offset), "{:?} at {}", unit, offset);
+            }
+        }
+    }
+
+    #[test]
+    fn locates_tokens() {
+        let source = "a = 1\n\tcall(\"中\", b)";
+        let index = LineIndex::new(source, 4);
+        let tokens = tokenize(source.to_string(), &crate::options::default());
+        let b = tokens.iter().find(|token| token.1.text == "b").unwrap();
+        assert_eq!(index.token_start(b, ColumnUnit::Chars), LineCol::new(1, 11));
+        assert_eq!(index.token_start(b, ColumnUnit::Display), LineCol::new(1, 15));
+        assert_eq!(index.token_end(b, ColumnUnit::Chars), LineCol::new(1, 12));
+    }
+}
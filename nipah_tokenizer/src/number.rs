@@ -0,0 +1,376 @@
+use std::sync::OnceLock;
+
+use crate::{token::TokenType, error::TokenizeErrorKind, decimal::Decimal};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Radix {
+    Binary = 2,
+    Octal = 8,
+    Decimal = 10,
+    Hexadecimal = 16
+}
+impl Radix {
+    fn from_prefix(c: char) -> Option<Radix> {
+        match c {
+            'b' | 'B' => Some(Radix::Binary),
+            'o' | 'O' => Some(Radix::Octal),
+            'x' | 'X' => Some(Radix::Hexadecimal),
+            _ => None
+        }
+    }
+}
+
+/// How a numeric literal was written in the source
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NumberLiteral {
+    pub radix: Radix,
+    /// Type suffix such as `u8` or `f32`, if any
+    pub suffix: Option<String>
+}
+
+/// A numeric literal kept exactly as written, converted to the precision the caller asks for on first use
+#[derive(Debug, Clone)]
+pub struct ExactNumber {
+    negative: bool,
+    radix: Radix,
+    digits: String,
+    magnitude: OnceLock<Option<u128>>,
+    decimal: OnceLock<Option<Decimal>>
+}
+impl ExactNumber {
+    pub(crate) fn new(negative: bool, radix: Radix, digits: String) -> ExactNumber {
+        ExactNumber {
+            negative,
+            radix,
+            digits,
+            magnitude: OnceLock::new(),
+            decimal: OnceLock::new()
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn radix(&self) -> Radix {
+        self.radix
+    }
+
+    /// Digits as written, without sign, radix prefix, separators or suffix
+    pub fn digits(&self) -> &str {
+        &self.digits
+    }
+
+    /// Absolute value of an integer literal, if it fits
+    fn magnitude(&self) -> Option<u128> {
+        *self.magnitude.get_or_init(|| u128::from_str_radix(&self.digits, self.radix as u32).ok())
+    }
+
+    pub fn to_u128(&self) -> Option<u128> {
+        match self.magnitude() {
+            Some(0) => Some(0),
+            Some(magnitude) if !self.negative => Some(magnitude),
+            _ => None
+        }
+    }
+
+    pub fn to_i128(&self) -> Option<i128> {
+        let magnitude = self.magnitude()?;
+        if self.negative {
+            if magnitude > i128::MAX as u128 + 1 { None } else { Some((magnitude as i128).wrapping_neg()) }
+        } else {
+            i128::try_from(magnitude).ok()
+        }
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        self.to_i128().and_then(|value| i64::try_from(value).ok())
+    }
+
+    /// Exact decimal value of the literal, only missing for non decimal integers too large for `u128`
+    pub fn to_decimal(&self) -> Option<&Decimal> {
+        self.decimal.get_or_init(|| match self.radix {
+            Radix::Decimal => Decimal::parse(&format!("{}{}", if self.negative { "-" } else { "" }, self.digits)),
+            _ => self.magnitude().map(|magnitude| Decimal::from_u128(magnitude, self.negative))
+        }).as_ref()
+    }
+
+    /// Closest `f64` to the literal
+    pub fn to_f64(&self) -> f64 {
+        match self.to_decimal() {
+            Some(decimal) => decimal.to_f64(),
+            None => {
+                let magnitude = self.digits.chars()
+                    .filter_map(|c| c.to_digit(self.radix as u32))
+                    .fold(0_f64, |value, digit| value * self.radix as u32 as f64 + digit as f64);
+                if self.negative { -magnitude } else { magnitude }
+            }
+        }
+    }
+}
+impl PartialEq for ExactNumber {
+    fn eq(&self, other: &ExactNumber) -> bool {
+        self.negative == other.negative && self.radix == other.radix && self.digits == other.digits
+    }
+}
+
+/// What an `ExactNumber` is serialized as, leaving out the conversions it caches
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ExactNumberRepr<'a> {
+    negative: bool,
+    radix: Radix,
+    #[serde(borrow)]
+    digits: std::borrow::Cow<'a, str>
+}
+#[cfg(feature = "serde")]
+impl serde::Serialize for ExactNumber {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ExactNumberRepr { negative: self.negative, radix: self.radix, digits: self.digits.as_str().into() }.serialize(serializer)
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ExactNumber {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = ExactNumberRepr::deserialize(deserializer)?;
+        Ok(ExactNumber::new(repr.negative, repr.radix, repr.digits.into_owned()))
+    }
+}
+
+const INTEGER_SUFFIXES: [&str; 12] = ["i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize"];
+const FLOAT_SUFFIXES: [&str; 3] = ["f", "f32", "f64"];
+
+/// Checks whether a piece of text starts like a numeric literal, with an optional minus sign
+pub fn is_number_start(text: &str) -> bool {
+    text.strip_prefix('-').unwrap_or(text).starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Finds where the numeric literal at the start of `text` ends, `text` must start with a digit
+pub(crate) fn scan_number(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut end = if bytes.len() > 1 && bytes[0] == b'0' && Radix::from_prefix(bytes[1] as char).is_some() {
+        digits_end(bytes, 2, |b| b.is_ascii_hexdigit())
+    } else {
+        decimal_end(bytes)
+    };
+    while end < bytes.len() && (bytes[end].is_ascii_alphanumeric() || bytes[end] == b'_') {
+        end += 1;
+    }
+    end
+}
+
+/// Parses a complete numeric literal, such as `-0xFF`, `1_000`, `6.02e23` or `2.5f32`.
+///
+/// With `exact` set, the literal is kept as an `ExactIntegerLiteral` or `ExactDecimalLiteral`,
+/// which only fails on values out of range for an explicit suffix.
+pub(crate) fn parse_number(text: &str, exact: bool) -> Result<(TokenType, NumberLiteral), TokenizeErrorKind> {
+    let (negative, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text)
+    };
+
+    let prefix = unsigned.strip_prefix('0')
+        .and_then(|rest| rest.chars().next())
+        .and_then(Radix::from_prefix);
+    let (radix, body) = match prefix {
+        Some(radix) => (radix, &unsigned[2..]),
+        None => (Radix::Decimal, unsigned)
+    };
+
+    let body_end = match radix {
+        Radix::Decimal => decimal_end(body.as_bytes()),
+        Radix::Hexadecimal => digits_end(body.as_bytes(), 0, u8::is_ascii_hexdigit),
+        _ => digits_end(body.as_bytes(), 0, u8::is_ascii_digit)
+    };
+    let (digits, suffix) = body.split_at(body_end);
+    let digits: String = digits.chars().filter(|c| *c != '_').collect();
+    let literal = NumberLiteral {
+        radix,
+        suffix: if suffix.is_empty() { None } else { Some(suffix.to_string()) }
+    };
+    if digits.is_empty() {
+        return Err(TokenizeErrorKind::InvalidNumber);
+    }
+
+    let is_float_syntax = radix == Radix::Decimal && digits.contains(['.', 'e', 'E']);
+    let is_float = is_float_syntax || FLOAT_SUFFIXES.contains(&suffix);
+    let known_suffix = INTEGER_SUFFIXES.contains(&suffix) || (radix == Radix::Decimal && FLOAT_SUFFIXES.contains(&suffix));
+    if !suffix.is_empty() && !known_suffix {
+        return Err(TokenizeErrorKind::InvalidNumberSuffix);
+    }
+    if is_float_syntax && INTEGER_SUFFIXES.contains(&suffix) {
+        return Err(TokenizeErrorKind::InvalidNumberSuffix);
+    }
+
+    if is_float && exact {
+        let number = ExactNumber::new(negative, radix, digits);
+        let decimal = number.to_decimal().ok_or(TokenizeErrorKind::InvalidNumber)?;
+        if suffix == "f32" && decimal.to_f64().abs() > f32::MAX as f64 {
+            return Err(TokenizeErrorKind::NumberOverflow);
+        }
+        return Ok((TokenType::ExactDecimalLiteral(number), literal));
+    }
+    if is_float {
+        let value = digits.parse::<f64>().map_err(|_| TokenizeErrorKind::InvalidNumber)?;
+        let limit = if suffix == "f32" { f32::MAX as f64 } else { f64::MAX };
+        if !value.is_finite() || value > limit {
+            return Err(TokenizeErrorKind::NumberOverflow);
+        }
+        return Ok((TokenType::FloatLiteral(if negative { -value } else { value }), literal));
+    }
+
+    let magnitude = match u128::from_str_radix(&digits, radix as u32) {
+        Ok(magnitude) => magnitude,
+        Err(err) if *err.kind() == std::num::IntErrorKind::PosOverflow => {
+            return if exact && suffix.is_empty() {
+                Ok((TokenType::ExactIntegerLiteral(ExactNumber::new(negative, radix, digits)), literal))
+            } else {
+                Err(TokenizeErrorKind::NumberOverflow)
+            };
+        },
+        Err(_) => return Err(TokenizeErrorKind::InvalidNumber)
+    };
+    if exact && suffix.is_empty() {
+        return Ok((TokenType::ExactIntegerLiteral(ExactNumber::new(negative, radix, digits)), literal));
+    }
+    let (min, max) = integer_range(suffix);
+    let in_range = if negative { magnitude <= min } else { magnitude <= max };
+    if !in_range {
+        return Err(TokenizeErrorKind::NumberOverflow);
+    }
+    let value = if negative { (magnitude as i128).wrapping_neg() } else { magnitude as i128 };
+    if exact {
+        return Ok((TokenType::ExactIntegerLiteral(ExactNumber::new(negative, radix, digits)), literal));
+    }
+    let value = i64::try_from(value).map_err(|_| TokenizeErrorKind::NumberOverflow)?;
+    Ok((TokenType::IntegerLiteral(value), literal))
+}
+
+/// Largest magnitude allowed for negative and positive values of an integer suffix
+fn integer_range(suffix: &str) -> (u128, u128) {
+    match suffix {
+        "i8" => (1 << 7, i8::MAX as u128),
+        "i16" => (1 << 15, i16::MAX as u128),
+        "i32" => (1 << 31, i32::MAX as u128),
+        "i128" => (1 << 127, i128::MAX as u128),
+        "u8" => (0, u8::MAX as u128),
+        "u16" => (0, u16::MAX as u128),
+        "u32" => (0, u32::MAX as u128),
+        "u64" | "usize" => (0, u64::MAX as u128),
+        "u128" => (0, u128::MAX),
+        _ => (1 << 63, i64::MAX as u128)
+    }
+}
+
+fn digits_end(bytes: &[u8], start: usize, is_digit: fn(&u8) -> bool) -> usize {
+    let mut end = start;
+    while end < bytes.len() && (is_digit(&bytes[end]) || bytes[end] == b'_') {
+        end += 1;
+    }
+    end
+}
+
+/// Finds the end of the digits, fraction and exponent of a decimal literal
+fn decimal_end(bytes: &[u8]) -> usize {
+    let mut end = digits_end(bytes, 0, u8::is_ascii_digit);
+    if end + 1 < bytes.len() && bytes[end] == b'.' && bytes[end + 1].is_ascii_digit() {
+        end = digits_end(bytes, end + 1, u8::is_ascii_digit);
+    }
+    if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+        let sign = if end + 1 < bytes.len() && (bytes[end + 1] == b'+' || bytes[end + 1] == b'-') { 1 } else { 0 };
+        if end + 1 + sign < bytes.len() && bytes[end + 1 + sign].is_ascii_digit() {
+            end = digits_end(bytes, end + 1 + sign, u8::is_ascii_digit);
+        }
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<TokenType, TokenizeErrorKind> {
+        parse_number(text, false).map(|(kind, _)| kind)
+    }
+
+    fn exact(text: &str) -> ExactNumber {
+        match parse_number(text, true) {
+            Ok((TokenType::ExactIntegerLiteral(number), _)) | Ok((TokenType::ExactDecimalLiteral(number), _)) => number,
+            other => panic!("{} parsed as {:?}", text, other)
+        }
+    }
+
+    #[test]
+    fn scans_whole_literals() {
+        assert_eq!(scan_number("0xFF)"), 4);
+        assert_eq!(scan_number("1_000_000;"), 9);
+        assert_eq!(scan_number("6.02e23 "), 7);
+        assert_eq!(scan_number("1e-9+"), 4);
+        assert_eq!(scan_number("2.5f32,"), 6);
+        assert_eq!(scan_number("1.foo"), 1);
+        assert_eq!(scan_number("1..2"), 1);
+    }
+
+    #[test]
+    fn parses_radixes() {
+        assert_eq!(parse("0xFF"), Ok(TokenType::IntegerLiteral(255)));
+        assert_eq!(parse("0b1010"), Ok(TokenType::IntegerLiteral(10)));
+        assert_eq!(parse("0o755"), Ok(TokenType::IntegerLiteral(493)));
+        assert_eq!(parse("-0x10"), Ok(TokenType::IntegerLiteral(-16)));
+        assert_eq!(parse_number("0xFFu8", false).unwrap().1, NumberLiteral { radix: Radix::Hexadecimal, suffix: Some("u8".to_string()) });
+    }
+
+    #[test]
+    fn parses_separators_exponents_and_suffixes() {
+        assert_eq!(parse("1_000_000"), Ok(TokenType::IntegerLiteral(1_000_000)));
+        assert_eq!(parse("6.02e23"), Ok(TokenType::FloatLiteral(6.02e23)));
+        assert_eq!(parse("1e-9"), Ok(TokenType::FloatLiteral(1e-9)));
+        assert_eq!(parse("10u8"), Ok(TokenType::IntegerLiteral(10)));
+        assert_eq!(parse("2.5f32"), Ok(TokenType::FloatLiteral(2.5)));
+        assert_eq!(parse("123f"), Ok(TokenType::FloatLiteral(123.0)));
+        assert_eq!(parse_number("2.5f32", false).unwrap().1, NumberLiteral { radix: Radix::Decimal, suffix: Some("f32".to_string()) });
+    }
+
+    #[test]
+    fn reports_overflow() {
+        assert_eq!(parse("9223372036854775807"), Ok(TokenType::IntegerLiteral(i64::MAX)));
+        assert_eq!(parse("-9223372036854775808"), Ok(TokenType::IntegerLiteral(i64::MIN)));
+        assert_eq!(parse("9223372036854775808"), Err(TokenizeErrorKind::NumberOverflow));
+        assert_eq!(parse("256u8"), Err(TokenizeErrorKind::NumberOverflow));
+        assert_eq!(parse("-1u32"), Err(TokenizeErrorKind::NumberOverflow));
+        assert_eq!(parse("1e999"), Err(TokenizeErrorKind::NumberOverflow));
+        assert_eq!(parse("1e39f32"), Err(TokenizeErrorKind::NumberOverflow));
+    }
+
+    #[test]
+    fn reports_malformed_literals() {
+        assert_eq!(parse("0x"), Err(TokenizeErrorKind::InvalidNumber));
+        assert_eq!(parse("0b102"), Err(TokenizeErrorKind::InvalidNumber));
+        assert_eq!(parse("12abc"), Err(TokenizeErrorKind::InvalidNumberSuffix));
+        assert_eq!(parse("1.5u8"), Err(TokenizeErrorKind::InvalidNumberSuffix));
+        assert_eq!(parse("0x1p3"), Err(TokenizeErrorKind::InvalidNumberSuffix));
+    }
+
+    #[test]
+    fn keeps_large_integers_exactly() {
+        let number = exact("18446744073709551616");
+        assert_eq!(number.to_u128(), Some(18446744073709551616));
+        assert_eq!(number.to_i64(), None);
+        assert_eq!(exact("-0x8000_0000_0000_0000_0000_0000_0000_0000").to_i128(), Some(i128::MIN));
+        assert_eq!(exact("-5").to_u128(), None);
+        assert_eq!(exact("340282366920938463463374607431768211456").to_u128(), None);
+        assert_eq!(exact("340282366920938463463374607431768211456").to_decimal().unwrap().to_string(), "340282366920938463463374607431768211456");
+    }
+
+    #[test]
+    fn keeps_decimals_exactly() {
+        let number = exact("0.1");
+        assert_eq!(number.to_decimal(), Decimal::parse("0.1").as_ref());
+        assert_eq!(number.to_f64(), 0.1);
+        assert_eq!(exact("1e999").to_decimal().unwrap().exponent(), 999);
+        assert_eq!(parse_number("1e39f32", true).map(|(kind, _)| kind), Err(TokenizeErrorKind::NumberOverflow));
+        assert_eq!(parse_number("300u8", true).map(|(kind, _)| kind), Err(TokenizeErrorKind::NumberOverflow));
+    }
+}
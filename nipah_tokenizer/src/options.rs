@@ -1,22 +1,55 @@
-use std::{sync::{atomic::AtomicI64}, rc::Rc};
+use std::sync::{atomic::AtomicI64, Arc};
 
 use lazy_static::lazy_static;
 
+#[allow(deprecated)]
 pub struct TokenizerOptions {
     pub separators: Vec<Separator>,
     pub scopes: Vec<Scope>,
+    /// Which characters break lines, `\r\n` counts as a single break when it does
+    pub line_endings: LineEndings,
+    /// Characters that break lines, used instead of `line_endings` when not empty
+    #[deprecated(note = "use `line_endings`")]
     pub end_of_lines: Vec<EndOfLine>,
     pub split_aggregators: Vec<SplitAggregator>,
-    pub try_id: Rc<fn(&str) -> bool>
+    /// Tells whether a piece is an identifier.
+    ///
+    /// This used to be an `Rc<fn(&str) -> bool>`, set it to the function itself instead: a function pointer is
+    /// already `Copy` and can be shared between threads.
+    pub try_id: fn(&str) -> bool,
+    /// When set, newlines are turned into `Newline`, `Indent` and `Dedent` tokens following the leading whitespace of each line
+    pub indentation: Option<Indentation>,
+    /// Produce `ExactIntegerLiteral` and `ExactDecimalLiteral` instead of the lossy `IntegerLiteral` and `FloatLiteral`
+    pub exact_numbers: bool,
+    /// Append an `EndOfInput` token after the last token
+    pub end_of_input: bool,
+    /// Produce `EOF` for line breaks as older versions did, instead of `Newline`
+    pub legacy_eof: bool
 }
 impl TokenizerOptions {
-    fn new(separators: Vec<Separator>, scopes: Vec<Scope>, end_of_lines: Vec<EndOfLine>, split_aggregators: Vec<SplitAggregator>) -> Self {
+    #[allow(deprecated)]
+    fn new(separators: Vec<Separator>, scopes: Vec<Scope>, line_endings: LineEndings, split_aggregators: Vec<SplitAggregator>) -> Self {
         TokenizerOptions {
             separators,
             scopes,
-            end_of_lines,
+            line_endings,
+            end_of_lines: vec![],
             split_aggregators,
-            try_id: Rc::new(default_try_id)
+            try_id: default_try_id,
+            indentation: None,
+            exact_numbers: false,
+            end_of_input: true,
+            legacy_eof: false
+        }
+    }
+
+    /// The line endings policy in effect, from `end_of_lines` when it was set
+    #[allow(deprecated)]
+    pub(crate) fn line_ending_policy(&self) -> LineEndings {
+        if self.end_of_lines.is_empty() {
+            self.line_endings
+        } else {
+            LineEndings::from_end_of_lines(&self.end_of_lines)
         }
     }
 }
@@ -35,14 +68,18 @@ lazy_static! {
     ];
     pub static ref DEFAULT_SCOPES: [Scope; 2] = [
         Scope::new('"', '"'),
-        Scope::new('\'', '\'')
-    ];
-    pub static ref DEFAULT_END_OF_LINES: [EndOfLine; 2] = [
-        EndOfLine::new('\n'),
-        EndOfLine::new('\0')
+        Scope::new_char('\'', '\'')
     ];
 }
 
+/// The line breaks of older versions, which `LineEndings::Lf` now stands for
+#[deprecated(note = "use `LineEndings`")]
+#[allow(deprecated)]
+pub const DEFAULT_END_OF_LINES: [EndOfLine; 2] = [
+    EndOfLine { eof: '\n' },
+    EndOfLine { eof: '\0' }
+];
+
 pub fn default_try_id(entry: &str) -> bool {
     if entry.is_empty() {
         return false;
@@ -51,7 +88,7 @@ pub fn default_try_id(entry: &str) -> bool {
     let mut chars = entry.chars();
 
     let first_valid = if let Some(frc) = chars.next() {
-        if frc.is_ascii_digit() || frc == '_' {
+        if frc.is_ascii_digit() || frc == '_' || frc == '-' {
             return false;
         }
         true
@@ -69,7 +106,7 @@ pub fn default_try_id(entry: &str) -> bool {
     true
 }
 
-pub fn default_split_aggregators() -> [SplitAggregator; 13] {
+pub fn default_split_aggregators() -> [SplitAggregator; 11] {
     [
         SplitAggregator::from_strings(vec!["=".to_string(), "=".to_string()]),
         SplitAggregator::from_strings(vec!["!".to_string(), "=".to_string()]),
@@ -81,61 +118,28 @@ pub fn default_split_aggregators() -> [SplitAggregator; 13] {
         SplitAggregator::from_strings(vec!["-".to_string(), ">".to_string()]),
         SplitAggregator::from_strings(vec!["=".to_string(), ">".to_string()]),
         SplitAggregator::from_strings(vec!["&".to_string(), "&".to_string()]),
-        SplitAggregator::from_strings(vec!["|".to_string(), "|".to_string()]),
-
-        SplitAggregator::new(vec![Rc::new(|x: &String| is_numeric(x, false)), Rc::new(|x: &String| x == "."), Rc::new(|x: &String| is_numeric(x, true))]),
-        SplitAggregator::new(vec![Rc::new(|x: &String| x == "-"), Rc::new(|x: &String| is_numeric_accept_float(x))])
+        SplitAggregator::from_strings(vec!["|".to_string(), "|".to_string()])
     ]
 }
 
-fn is_numeric(x: &str, accept_f: bool) -> bool {
-    let chars = x.chars();
-    for c in chars {
-        if c == '-' {
-            continue;
-        }
-        if c == 'f' {
-            if !accept_f {
-                return false;
-            }
-            continue;
-        }
-        if !c.is_ascii_digit() {
-            return false;
-        }
-    }
-    true
-}
-fn is_numeric_accept_float(x: &str) -> bool {
-    let chars = x.chars();
-    let mut has_dot = false;
-    for c in chars {
-        if c == '.' {
-            if has_dot {
-                return false;
-            }
-            has_dot = true;
-            continue;
-        }
-        if c == 'f' {
-            continue;
-        }
-        if !c.is_ascii_digit() {
-            return false;
-        }
-    }
-    true
-}
-
 pub fn default() -> TokenizerOptions {
     TokenizerOptions::new(
         DEFAULT_SEPARATORS.to_vec(),
          DEFAULT_SCOPES.to_vec(),
-          DEFAULT_END_OF_LINES.to_vec(),
+          LineEndings::Any,
            default_split_aggregators().to_vec()
     )
 }
 
+/// The default options, but with line breaks as `EOF` tokens and no `EndOfInput`, as older versions produced
+pub fn legacy() -> TokenizerOptions {
+    TokenizerOptions {
+        end_of_input: false,
+        legacy_eof: true,
+        ..default()
+    }
+}
+
 #[derive(Clone)]
 pub struct Separator {
     pub cmatch: char,
@@ -161,7 +165,11 @@ impl Separator {
 pub struct Scope {
     pub id: i64,
     pub begin: char,
-    pub end: char
+    pub end: char,
+    /// Escape sequences understood inside the scope, `None` keeps backslashes as they are
+    pub escapes: Option<Escapes>,
+    /// What kind of literal the scope produces
+    pub kind: ScopeKind
 }
 impl Scope {
     fn new(begin: char, end: char) -> Self {
@@ -170,24 +178,187 @@ impl Scope {
         Scope {
             id: COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst),
             begin,
-            end
+            end,
+            escapes: Some(default_escapes()),
+            kind: ScopeKind::String
+        }
+    }
+
+    fn new_char(begin: char, end: char) -> Self {
+        Scope {
+            kind: ScopeKind::Char,
+            ..Scope::new(begin, end)
         }
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ScopeKind {
+    /// Produces a `StringLiteral` with any content
+    String,
+    /// Produces a `CharLiteral`, and an error unless the content is exactly one character
+    Char
+}
+
+#[derive(Clone)]
+pub struct Escapes {
+    /// Escapes made of a single character after the backslash, as `(escape, value)`
+    pub simple: Vec<(char, char)>,
+    /// `\u{1F600}`, and `\u00e9` with surrogate pairs such as `\uD83D\uDE00`
+    pub unicode: bool,
+    /// `\x41`
+    pub hex: bool,
+    /// A backslash at the end of a line skips the line break and the indentation after it
+    pub line_continuation: bool
+}
+
+pub fn default_escapes() -> Escapes {
+    Escapes {
+        simple: vec![('n', '\n'), ('r', '\r'), ('t', '\t'), ('0', '\0'), ('\\', '\\'), ('"', '"'), ('\'', '\'')],
+        unicode: true,
+        hex: true,
+        line_continuation: true
+    }
+}
+
+#[deprecated(note = "use `LineEndings`")]
 #[derive(Clone)]
 pub struct EndOfLine {
     pub eof: char
 }
-impl EndOfLine {
-    fn new(eof: char) -> EndOfLine {
-        EndOfLine {
-            eof
+
+/// A single line break as written in the source
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LineEnding {
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    CrLf,
+    /// `\r`
+    Cr,
+    /// U+2028
+    LineSeparator,
+    /// U+2029
+    ParagraphSeparator
+}
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+            LineEnding::LineSeparator => "\u{2028}",
+            LineEnding::ParagraphSeparator => "\u{2029}"
+        }
+    }
+
+    /// Gets the line break `text` starts with, if any
+    pub fn at(text: &str) -> Option<LineEnding> {
+        if text.starts_with("\r\n") {
+            return Some(LineEnding::CrLf);
+        }
+        match text.chars().next()? {
+            '\n' => Some(LineEnding::Lf),
+            '\r' => Some(LineEnding::Cr),
+            '\u{2028}' => Some(LineEnding::LineSeparator),
+            '\u{2029}' => Some(LineEnding::ParagraphSeparator),
+            _ => None
+        }
+    }
+}
+
+/// Which line breaks end a line.
+///
+/// Line break characters that the policy does not accept are treated as blank space.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LineEndings {
+    Lf,
+    CrLf,
+    Cr,
+    /// LF, CRLF, CR, U+2028 and U+2029
+    Any,
+    /// Whichever of LF, CRLF or CR comes first in the source
+    Auto
+}
+impl LineEndings {
+    /// The policy closest to a list of line break characters as older versions took them.
+    ///
+    /// Only `\n`, `\r`, U+2028 and U+2029 still break lines: `\n` alone is `Lf`, `\r` alone is `Cr` and any
+    /// other mix is `Any`. A list with none of them is `Lf`, like `DEFAULT_END_OF_LINES`.
+    #[deprecated(note = "use `LineEndings`")]
+    #[allow(deprecated)]
+    pub fn from_end_of_lines(end_of_lines: &[EndOfLine]) -> LineEndings {
+        let has = |c: char| end_of_lines.iter().any(|end_of_line| end_of_line.eof == c);
+        match (has('\n'), has('\r'), has('\u{2028}') || has('\u{2029}')) {
+            (_, false, false) => LineEndings::Lf,
+            (false, true, false) => LineEndings::Cr,
+            _ => LineEndings::Any
+        }
+    }
+
+    /// Replaces `Auto` by the policy detected in `text`
+    pub fn resolve(self, text: &str) -> LineEndings {
+        if self != LineEndings::Auto {
+            return self;
+        }
+        let first = text.char_indices().find_map(|(i, _)| LineEnding::at(&text[i..]));
+        match first {
+            Some(LineEnding::Lf) => LineEndings::Lf,
+            Some(LineEnding::CrLf) => LineEndings::CrLf,
+            Some(LineEnding::Cr) => LineEndings::Cr,
+            _ => LineEndings::Any
+        }
+    }
+
+    /// Gets the line break `text` starts with, if the policy accepts it
+    pub fn line_break_at(self, text: &str) -> Option<LineEnding> {
+        match self {
+            LineEndings::Lf => text.starts_with('\n').then_some(LineEnding::Lf),
+            LineEndings::CrLf => text.starts_with("\r\n").then_some(LineEnding::CrLf),
+            LineEndings::Cr => text.starts_with('\r').then_some(LineEnding::Cr),
+            LineEndings::Any | LineEndings::Auto => LineEnding::at(text)
+        }
+    }
+
+    /// Byte ranges of the lines of `text`, without their line breaks
+    pub(crate) fn lines(self, text: &str) -> Vec<(usize, usize)> {
+        let line_endings = self.resolve(text);
+        let mut lines = vec![];
+        let mut start = 0;
+        let mut i = 0;
+        while let Some(c) = text[i..].chars().next() {
+            match line_endings.line_break_at(&text[i..]) {
+                Some(ending) => {
+                    lines.push((start, i));
+                    i += ending.as_str().len();
+                    start = i;
+                },
+                None => i += c.len_utf8()
+            }
         }
+        lines.push((start, text.len()));
+        lines
     }
 }
 
-pub type SplitAggregatorFn = Rc<dyn Fn(&String) -> bool + Send + Sync>;
+#[derive(Clone)]
+pub struct Indentation {
+    pub tab_width: usize
+}
+impl Indentation {
+    pub fn new(tab_width: usize) -> Indentation {
+        Indentation {
+            tab_width
+        }
+    }
+}
+
+/// Tells whether a piece is the one expected at its place in a `SplitAggregator`.
+///
+/// Detectors used to be `Rc<dyn Fn(&String) -> bool + Send + Sync>`, closures of that shape can still be made
+/// into one with `SplitAggregator::string_detector`.
+pub type SplitAggregatorFn = Arc<dyn Fn(&str) -> bool + Send + Sync>;
 #[derive(Clone)]
 pub struct SplitAggregator {
     pub detectors: Vec<SplitAggregatorFn>
@@ -207,8 +378,12 @@ impl SplitAggregator {
             detectors: fin
         }
     }
+    /// A detector from a closure taking `&String`, as detectors were written before they took `&str`
+    pub fn string_detector(detector: impl Fn(&String) -> bool + Send + Sync + 'static) -> SplitAggregatorFn {
+        Arc::new(move |cmp: &str| detector(&cmp.to_string()))
+    }
     fn string_matcher(detector: String) -> SplitAggregatorFn {
-        Rc::new(move |cmp: &String| *cmp == detector)
+        Arc::new(move |cmp: &str| cmp == detector)
     }
 }
 
@@ -227,4 +402,40 @@ mod tests {
         let id = "1hello_world".to_string();
         assert!(!default_try_id(&id));
     }
+
+    #[test]
+    fn try_default_id_fail_on_negative_number() {
+        let id = "-5".to_string();
+        assert!(!default_try_id(&id));
+    }
+
+    #[test]
+    fn try_default_id_ok_on_non_ascii_start() {
+        assert!(default_try_id("é"));
+        assert!(default_try_id("ñame"));
+    }
+
+    #[test]
+    fn line_endings_detect_the_first_break() {
+        assert_eq!(LineEndings::Auto.resolve("a\r\nb\nc"), LineEndings::CrLf);
+        assert_eq!(LineEndings::Auto.resolve("a\rb"), LineEndings::Cr);
+        assert_eq!(LineEndings::Auto.resolve("a"), LineEndings::Any);
+        assert_eq!(LineEndings::Any.lines("a\r\nb\u{2029}c\n"), vec![(0, 1), (3, 4), (7, 8), (9, 9)]);
+        assert_eq!(LineEndings::Cr.line_break_at("\r\n"), Some(LineEnding::Cr));
+        assert_eq!(LineEndings::CrLf.line_break_at("\n"), None);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn end_of_lines_map_onto_line_endings() {
+        assert_eq!(LineEndings::from_end_of_lines(&DEFAULT_END_OF_LINES), LineEndings::Lf);
+        assert_eq!(LineEndings::from_end_of_lines(&[EndOfLine { eof: '\r' }]), LineEndings::Cr);
+        assert_eq!(LineEndings::from_end_of_lines(&[EndOfLine { eof: '\r' }, EndOfLine { eof: '\n' }]), LineEndings::Any);
+
+        let mut options = default();
+        options.line_endings = LineEndings::CrLf;
+        assert_eq!(options.line_ending_policy(), LineEndings::CrLf);
+        options.end_of_lines = DEFAULT_END_OF_LINES.to_vec();
+        assert_eq!(options.line_ending_policy(), LineEndings::Lf);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,157 @@
+use std::{ops::Range, panic, thread, num::NonZeroUsize, sync::atomic::{AtomicUsize, Ordering}};
+
+use crate::{options::{TokenizerOptions, LineEndings, Scope}, token::Token, indentation, stream::BoundaryScanner, tokenizer::{tokenize_str, tokenize_piece, end_of_input}};
+
+/// Bytes of one input each thread tokenizes at least, when splitting it with `tokenize_chunked`
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// Threads used when none are given, one per core available
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1)
+}
+
+/// Tokenizes many inputs at once, giving their tokens in the same order as the inputs
+pub fn tokenize_all<S: AsRef<str> + Sync>(inputs: &[S], options: &TokenizerOptions) -> Vec<Vec<Token>> {
+    tokenize_all_on(inputs, options, default_threads())
+}
+
+/// Tokenizes many inputs at once on `threads` threads, giving their tokens in the same order as the inputs
+pub fn tokenize_all_on<S: AsRef<str> + Sync>(inputs: &[S], options: &TokenizerOptions, threads: usize) -> Vec<Vec<Token>> {
+    run_pool(inputs.len(), threads, |index| tokenize_str(inputs[index].as_ref(), options))
+}
+
+/// Tokenizes one large input on many threads, giving the same tokens as `tokenize`
+pub fn tokenize_chunked(entry: &str, options: &TokenizerOptions) -> Vec<Token> {
+    tokenize_chunked_on(entry, options, DEFAULT_CHUNK_SIZE, default_threads())
+}
+
+/// Tokenizes one large input on `threads` threads, giving the same tokens as `tokenize`.
+///
+/// The input is cut at line breaks outside of scopes into pieces of at least `chunk_size` bytes, found by
+/// scanning it once before tokenizing. Each piece is tokenized by itself and the positions are corrected after.
+pub fn tokenize_chunked_on(entry: &str, options: &TokenizerOptions, chunk_size: usize, threads: usize) -> Vec<Token> {
+    let line_endings = options.line_ending_policy().resolve(entry);
+    let chunks = safe_chunks(entry, &options.scopes, line_endings, chunk_size);
+    let pieces = run_pool(chunks.len(), threads, |index| {
+        let chunk = chunks[index].clone();
+        let tokens = tokenize_piece(&entry[chunk.clone()], line_endings, options, chunk.start, 0);
+        (tokens, line_endings.lines(&entry[chunk]).len() - 1)
+    });
+
+    let mut tokens = Vec::with_capacity(pieces.iter().map(|(tokens, _)| tokens.len()).sum::<usize>() + 1);
+    let mut line = 0;
+    let mut last_line = 0;
+    for (piece, lines) in pieces {
+        tokens.extend(piece.into_iter().map(|mut token| {
+            token.1.position.line += line as i32;
+            token
+        }));
+        last_line = line;
+        line += lines;
+    }
+
+    if let Some(indentation) = &options.indentation {
+        tokens = indentation::apply_indentation(tokens, &indentation::measure_lines(entry, indentation, line_endings));
+    }
+    if options.end_of_input {
+        let last = chunks.last().map_or(0, |chunk| chunk.start);
+        tokens.push(end_of_input(&entry[last..], line_endings, last_line, last));
+    }
+    tokens
+}
+
+/// Cuts `text` after line breaks outside of scopes, into pieces of at least `chunk_size` bytes save for the last one
+fn safe_chunks(text: &str, scopes: &[Scope], line_endings: LineEndings, chunk_size: usize) -> Vec<Range<usize>> {
+    let mut chunks = vec![];
+    let mut scanner = BoundaryScanner::new();
+    let mut start = 0;
+    while let Some(end) = scanner.next(text, scopes, line_endings) {
+        if end - start >= chunk_size {
+            chunks.push(start..end);
+            start = end;
+        }
+    }
+    if start < text.len() || chunks.is_empty() {
+        chunks.push(start..text.len());
+    }
+    chunks
+}
+
+/// Runs `job` for every index below `count` on up to `threads` threads, giving the results in index order
+fn run_pool<T: Send>(count: usize, threads: usize, job: impl Fn(usize) -> T + Sync) -> Vec<T> {
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<Option<T>> = (0..count).map(|_| None).collect();
+    thread::scope(|scope| {
+        let (next, job) = (&next, &job);
+        let workers: Vec<_> = (0..threads.clamp(1, count.max(1)))
+            .map(|_| scope.spawn(move || {
+                let mut done = vec![];
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= count {
+                        break done;
+                    }
+                    done.push((index, job(index)));
+                }
+            }))
+            .collect();
+
+        for worker in workers {
+            let done = worker.join().unwrap_or_else(|err| panic::resume_unwind(err));
+            for (index, result) in done {
+                results[index] = Some(result);
+            }
+        }
+    });
+    results.into_iter().map(Option::unwrap).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{options::{self, Indentation}, tokenizer::tokenize};
+
+    use super::*;
+
+    const SOURCES: [&str; 5] = [
+        "let x = \"multi\nline \\\" string\";\r\ny = 'é' + 0x1F; // 中文\r\n\n  z(a,\n b)\n",
+        "if a:\n    b = \"\\u{1F600}\"\n    c\r\n\td\n",
+        "first\rsecond\u{2028}\"unterminated\n",
+        "no line breaks at all",
+        ""
+    ];
+
+    #[test]
+    fn tokenizes_many_inputs_in_order() {
+        let options = options::default();
+        let expected: Vec<_> = SOURCES.iter().map(|source| tokenize(source.to_string(), &options)).collect();
+        for threads in [1, 2, 8] {
+            assert_eq!(tokenize_all_on(&SOURCES, &options, threads), expected);
+        }
+        assert!(tokenize_all::<String>(&[], &options).is_empty());
+    }
+
+    #[test]
+    fn chunked_matches_sequential_tokenize() {
+        let mut indented = options::default();
+        indented.indentation = Some(Indentation::new(4));
+        let mut auto = options::default();
+        auto.line_endings = LineEndings::Auto;
+
+        let all = SOURCES.concat().repeat(3);
+        for options in [options::default(), indented, auto, options::legacy()] {
+            for source in SOURCES.iter().copied().chain([all.as_str()]) {
+                let expected = tokenize(source.to_string(), &options);
+                for chunk_size in [1, 2, 7, 40, DEFAULT_CHUNK_SIZE] {
+                    assert_eq!(tokenize_chunked_on(source, &options, chunk_size, 3), expected, "{:?} in chunks of {}", source, chunk_size);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chunks_end_at_line_breaks_outside_scopes() {
+        let text = "a\n\"b\nc\"\nd";
+        let chunks = safe_chunks(text, &options::DEFAULT_SCOPES[..], LineEndings::Any, 1);
+        assert_eq!(chunks, vec![0..2, 2..8, 8..9]);
+    }
+}
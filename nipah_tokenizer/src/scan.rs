@@ -0,0 +1,134 @@
+use crate::options::TokenizerOptions;
+
+const ONES: u64 = 0x0101_0101_0101_0101;
+const HIGHS: u64 = ONES * 0x80;
+const LOWS: u64 = ONES * 0x7F;
+/// Stop bytes are tested in blocks as long as they make up no more ranges than this
+const MAX_RANGES: usize = 16;
+
+/// Bytes that end a run of plain word characters: separators, scope openings and line breaks.
+///
+/// Only ASCII bytes are kept, every byte outside of ASCII stops a run as well so it gets a closer look.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct StopBytes {
+    mask: u128,
+    /// The stop bytes as inclusive ranges, `None` when there are too many of them
+    ranges: Option<([(u8, u8); MAX_RANGES], usize)>
+}
+impl StopBytes {
+    pub(crate) fn new(options: &TokenizerOptions) -> Self {
+        let separators = options.separators.iter().map(|separator| separator.cmatch);
+        let scopes = options.scopes.iter().map(|scope| scope.begin);
+        let mut mask = 0_u128;
+        for c in separators.chain(scopes).chain(['\n', '\r']).filter(char::is_ascii) {
+            mask |= 1 << c as u32;
+        }
+
+        let mut ranges = [(0, 0); MAX_RANGES];
+        let mut count = 0;
+        let mut byte = 0;
+        while byte < 128 {
+            if mask & (1 << byte) == 0 {
+                byte += 1;
+                continue;
+            }
+            let start = byte;
+            while byte < 128 && mask & (1 << byte) != 0 {
+                byte += 1;
+            }
+            if count < MAX_RANGES {
+                ranges[count] = (start as u8, byte as u8 - 1);
+            }
+            count += 1;
+        }
+        StopBytes {
+            mask,
+            ranges: (count <= MAX_RANGES).then_some((ranges, count))
+        }
+    }
+
+    pub(crate) fn contains(self, byte: u8) -> bool {
+        byte >= 0x80 || self.mask & (1 << byte) != 0
+    }
+
+    /// Length of the run of plain bytes `bytes` starts with, looking at eight of them at a time
+    pub(crate) fn plain_len(self, bytes: &[u8]) -> usize {
+        let Some((ranges, count)) = self.ranges else {
+            return self.plain_len_scalar(bytes);
+        };
+        // Most runs are short words, which are done before a whole block would even be read
+        let head = self.plain_len_scalar(&bytes[..bytes.len().min(8)]);
+        if head < 8 {
+            return head;
+        }
+
+        let mut blocks = bytes[8..].chunks_exact(8);
+        let mut offset = 8;
+        for block in &mut blocks {
+            let found = find_in_block(u64::from_le_bytes(block.try_into().unwrap()), &ranges[..count]);
+            if found != 0 {
+                return offset + found.trailing_zeros() as usize / 8;
+            }
+            offset += 8;
+        }
+        offset + self.plain_len_scalar(blocks.remainder())
+    }
+
+    /// Length of the run of plain bytes `bytes` starts with, looking at one at a time
+    pub(crate) fn plain_len_scalar(self, bytes: &[u8]) -> usize {
+        bytes.iter().position(|byte| self.contains(*byte)).unwrap_or(bytes.len())
+    }
+}
+
+/// Sets the high bit of the bytes of `block` that are outside of ASCII or inside one of `ranges`.
+///
+/// On the low seven bits of each byte, adding `128 - start` sets the high bit from `start` up, and subtracting
+/// from `128 + end` keeps it up to `end`. Neither can carry into the next byte.
+fn find_in_block(block: u64, ranges: &[(u8, u8)]) -> u64 {
+    let low = block & LOWS;
+    let mut found = block & HIGHS;
+    for &(start, end) in ranges {
+        let above = low + ONES * (128 - start as u64);
+        let below = ONES * (128 + end as u64) - low;
+        found |= above & below & !block & HIGHS;
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::{self, Separator, IncludeMode};
+
+    use super::*;
+
+    #[test]
+    fn stops_at_separators_scopes_and_line_breaks() {
+        let stops = StopBytes::new(&options::default());
+        assert_eq!(stops.plain_len(b"identifier_with_digits_123 = 4"), 26);
+        assert_eq!(stops.plain_len(b"abcdefghijklmnop\"q"), 16);
+        assert_eq!(stops.plain_len(b"abcdefgh\r\n"), 8);
+        assert_eq!(stops.plain_len("abcdefghé".as_bytes()), 8);
+        assert_eq!(stops.plain_len(b"abc"), 3);
+        assert_eq!(stops.plain_len(b""), 0);
+    }
+
+    #[test]
+    fn blocks_agree_with_scalar_scanning() {
+        let mut crowded = options::default();
+        crowded.separators = (b'!'..=b'~').step_by(3).map(|byte| Separator { cmatch: byte as char, include: IncludeMode::Separate }).collect();
+
+        for options in [options::default(), crowded] {
+            let stops = StopBytes::new(&options);
+            // Every byte value at every place of a run longer than a few blocks
+            for byte in 0..=u8::MAX {
+                for place in 0..40 {
+                    let mut bytes = [b'a'; 40];
+                    bytes[place] = byte;
+                    for start in 0..bytes.len() {
+                        assert_eq!(stops.plain_len(&bytes[start..]), stops.plain_len_scalar(&bytes[start..]), "{:?}", &bytes[start..]);
+                    }
+                }
+            }
+        }
+    }
+}
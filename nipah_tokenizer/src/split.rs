@@ -1,24 +1,105 @@
-use std::ops::Add;
+use std::{ops::Add, borrow::Cow};
 
-use crate::token::TokenPosition;
+use crate::{token::{TokenPosition, Span}, error::TokenizeErrorKind, options::TokenizerOptions, escape};
 
+/// A piece of the source found by splitting, borrowing its text from the source whenever it can
 #[derive(Debug, PartialEq, Clone)]
+pub struct Piece<'s> {
+    /// The source at `span`, except for pieces aggregated across blank space
+    pub text: Cow<'s, str>,
+    pub position: TokenPosition,
+    pub span: Span,
+    /// Set for pieces of malformed input
+    pub error: Option<TokenizeErrorKind>,
+    /// Id of the scope the piece was read in
+    pub scope: Option<i64>,
+    /// Whether the scope the piece was read in was closed, always true outside of scopes
+    pub closed: bool
+}
+impl<'s> Piece<'s> {
+    pub(crate) fn new(text: &'s str, position: i32, line: i32, span: Span) -> Piece<'s> {
+        Piece {
+            text: Cow::Borrowed(text),
+            position: TokenPosition::new(position, line),
+            span,
+            error: None,
+            scope: None,
+            closed: true
+        }
+    }
+
+    /// Content of a closed scope, without its delimiters and with escapes applied
+    pub fn cooked(&self, options: &TokenizerOptions) -> Option<Cow<'_, str>> {
+        let scope = options.scopes.iter().find(|scope| Some(scope.id) == self.scope)?;
+        if !self.closed {
+            return None;
+        }
+        let content = &self.text[scope.begin.len_utf8()..self.text.len() - scope.end.len_utf8()];
+        Some(escape::cook(content, scope.escapes.as_ref()))
+    }
+
+    /// Copies the text out of the source
+    pub fn into_item(self, options: &TokenizerOptions) -> SplitItem {
+        let cooked = self.cooked(options).map(Cow::into_owned);
+        SplitItem {
+            text: self.text.into_owned(),
+            position: self.position,
+            span: self.span,
+            error: self.error,
+            scope: self.scope,
+            cooked
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct SplitItem {
     pub text: String,
-    pub position: TokenPosition
+    pub position: TokenPosition,
+    pub span: Span,
+    /// Set for pieces of malformed input
+    pub error: Option<TokenizeErrorKind>,
+    /// Id of the scope the piece was read in
+    pub scope: Option<i64>,
+    /// Content of a closed scope, without its delimiters and with escapes applied
+    pub cooked: Option<String>
 }
 impl SplitItem {
     pub fn new(text: String, position: TokenPosition) -> SplitItem {
         SplitItem {
             text,
-            position
+            position,
+            span: Span::default(),
+            error: None,
+            scope: None,
+            cooked: None
         }
     }
 
     pub fn new_pl(text: String, position: i32, line: i32) -> SplitItem {
+        SplitItem::new_spanned(text, position, line, Span::default())
+    }
+
+    /// An item read from `span` of the source
+    pub fn new_spanned(text: String, position: i32, line: i32, span: Span) -> SplitItem {
         SplitItem {
             text,
-            position: TokenPosition::new(position, line)
+            position: TokenPosition::new(position, line),
+            span,
+            error: None,
+            scope: None,
+            cooked: None
+        }
+    }
+
+    pub fn error(kind: TokenizeErrorKind, text: String, position: i32, line: i32, span: Span) -> SplitItem {
+        SplitItem {
+            text,
+            position: TokenPosition::new(position, line),
+            span,
+            error: Some(kind),
+            scope: None,
+            cooked: None
         }
     }
 }
@@ -29,7 +110,11 @@ impl Add for SplitItem {
     fn add(self, rhs: SplitItem) -> SplitItem {
         SplitItem {
             text: self.text + &rhs.text,
-            position: self.position + rhs.position
+            position: self.position + rhs.position,
+            span: self.span.to(rhs.span),
+            error: self.error.or(rhs.error),
+            scope: None,
+            cooked: None
         }
     }
 }
@@ -42,16 +127,28 @@ mod tests {
     fn test_add() {
         let split_item = SplitItem {
             text: "Hello,".to_string(),
-            position: TokenPosition::new(0, 0)
+            position: TokenPosition::new(0, 0),
+            ..SplitItem::default()
         };
         let sum_item = SplitItem {
             text: " World!".to_string(),
-            position: TokenPosition::new(0, 0)
+            position: TokenPosition::new(0, 0),
+            ..SplitItem::default()
         };
 
         assert_eq!(split_item + sum_item, SplitItem {
             text: "Hello, World!".to_string(),
-            position: TokenPosition::new(0, 0)
+            position: TokenPosition::new(0, 0),
+            ..SplitItem::default()
         });
     }
+
+    #[test]
+    fn test_add_joins_spans() {
+        let split_item = SplitItem::new_spanned("Hello,".to_string(), 0, 0, Span::new(0, 6));
+        let sum_item = SplitItem::new_spanned(" World!".to_string(), 0, 0, Span::new(6, 13));
+
+        assert_eq!(split_item + sum_item, SplitItem::new_spanned("Hello, World!".to_string(), 0, 0, Span::new(0, 13)));
+        assert_eq!(SplitItem::new_pl("a".to_string(), 1, 2).span, Span::default());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,258 @@
+use crate::{options::{TokenizerOptions, LineEndings, Scope}, token::Token, error::{DecodeError, DecodeErrorKind}, encoding::UTF8_BOM, indentation::{self, Indenter}, tokenizer::{tokenize_piece, end_of_input}};
+
+/// Tokenizes input that arrives a piece at a time.
+///
+/// Text is held back until a line break outside of any scope, then everything up to it is tokenized.
+/// The tokens, positions and spans are the same `tokenize` gives for the whole input at once.
+pub struct StreamTokenizer<'o> {
+    options: &'o TokenizerOptions,
+    /// Text received but not tokenized yet
+    pending: String,
+    /// Bytes of a UTF-8 sequence that is not complete yet
+    partial: Vec<u8>,
+    /// Bytes decoded so far, for the offsets of decoding errors
+    bytes_read: usize,
+    started: bool,
+    /// Byte offset and line where `pending` starts
+    offset: usize,
+    line: usize,
+    /// Known once the first line break is seen, when the options ask for `LineEndings::Auto`
+    line_endings: Option<LineEndings>,
+    scanner: BoundaryScanner,
+    indenter: Option<Indenter>
+}
+impl<'o> StreamTokenizer<'o> {
+    pub fn new(options: &'o TokenizerOptions) -> Self {
+        StreamTokenizer {
+            options,
+            pending: String::new(),
+            partial: vec![],
+            bytes_read: 0,
+            started: false,
+            offset: 0,
+            line: 0,
+            line_endings: (options.line_endings != LineEndings::Auto).then_some(options.line_endings),
+            scanner: BoundaryScanner::new(),
+            indenter: options.indentation.as_ref().map(|_| Indenter::new())
+        }
+    }
+
+    /// Adds UTF-8 bytes, which may end in the middle of a character, and gives the tokens completed by them.
+    ///
+    /// A byte order mark at the very start is skipped.
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> Result<Vec<Token>, DecodeError> {
+        let mut data = std::mem::take(&mut self.partial);
+        data.extend_from_slice(bytes);
+
+        let mut start = 0;
+        if !self.started {
+            if data.len() < UTF8_BOM.len() && UTF8_BOM.starts_with(&data) {
+                self.partial = data;
+                return Ok(vec![]);
+            }
+            if data.starts_with(UTF8_BOM) {
+                start = UTF8_BOM.len();
+            }
+        }
+
+        let (text, rest) = match std::str::from_utf8(&data[start..]) {
+            Ok(text) => (text, &[][..]),
+            // The last character continues in the next bytes
+            Err(err) if err.error_len().is_none() => {
+                let (valid, rest) = data[start..].split_at(err.valid_up_to());
+                (std::str::from_utf8(valid).unwrap(), rest)
+            },
+            Err(err) => return Err(DecodeError::new(DecodeErrorKind::InvalidUtf8, self.bytes_read + start + err.valid_up_to()))
+        };
+        self.bytes_read += start + text.len();
+        let tokens = self.push_str(text);
+        self.partial = rest.to_vec();
+        Ok(tokens)
+    }
+
+    /// Adds text and gives the tokens completed by it
+    pub fn push_str(&mut self, text: &str) -> Vec<Token> {
+        self.started = true;
+        self.pending.push_str(text);
+
+        // A `\r` at the end may still turn out to start a `\r\n`
+        let scannable = &self.pending[..self.pending.len() - usize::from(self.pending.ends_with('\r'))];
+        if self.line_endings.is_none() && scannable.contains(['\n', '\r', '\u{2028}', '\u{2029}']) {
+            self.line_endings = Some(self.options.line_ending_policy().resolve(scannable));
+        }
+        let Some(line_endings) = self.line_endings else {
+            return vec![];
+        };
+
+        match self.scanner.scan(scannable, &self.options.scopes, line_endings) {
+            Some(end) => {
+                let chunk: String = self.pending.drain(..end).collect();
+                self.scanner.scanned -= end;
+                let tokens = self.tokenize_chunk(&chunk, line_endings);
+                self.offset += chunk.len();
+                self.line += line_endings.lines(&chunk).len() - 1;
+                tokens
+            },
+            None => vec![]
+        }
+    }
+
+    /// Tokenizes whatever is left once there is no more input
+    pub fn finish(mut self) -> Result<Vec<Token>, DecodeError> {
+        if !self.partial.is_empty() {
+            return Err(DecodeError::new(DecodeErrorKind::InvalidUtf8, self.bytes_read));
+        }
+
+        let rest = std::mem::take(&mut self.pending);
+        let line_endings = self.line_endings.unwrap_or_else(|| self.options.line_ending_policy().resolve(&rest));
+        let mut tokens = self.tokenize_chunk(&rest, line_endings);
+        if let Some(indenter) = self.indenter.take() {
+            indenter.finish(&mut tokens);
+        }
+        if self.options.end_of_input {
+            tokens.push(end_of_input(&rest, line_endings, self.line, self.offset));
+        }
+        Ok(tokens)
+    }
+
+    fn tokenize_chunk(&mut self, chunk: &str, line_endings: LineEndings) -> Vec<Token> {
+        let (offset, line) = (self.offset, self.line);
+        let lines = self.options.indentation.as_ref()
+            .map(|indentation| {
+                let mut lines = indentation::measure_lines(chunk, indentation, line_endings);
+                for indent in &mut lines {
+                    indent.start += offset;
+                }
+                lines
+            });
+
+        let tokens = tokenize_piece(chunk, line_endings, self.options, offset, line);
+        match (&mut self.indenter, &lines) {
+            (Some(indenter), Some(lines)) => {
+                let mut indented = vec![];
+                for token in tokens {
+                    indenter.push(token, lines, line, &mut indented);
+                }
+                indented
+            },
+            _ => tokens
+        }
+    }
+}
+
+/// Finds line breaks outside of scopes, where tokenizing can stop and start again without changing the tokens
+#[derive(Clone, Default)]
+pub(crate) struct BoundaryScanner {
+    /// Index of the scope the text scanned so far ends in
+    scope: Option<usize>,
+    /// Whether the last character scanned was an escaping backslash
+    escaped: bool,
+    /// Bytes already scanned
+    pub scanned: usize
+}
+impl BoundaryScanner {
+    pub(crate) fn new() -> Self {
+        BoundaryScanner::default()
+    }
+
+    /// Scans the part of `text` not scanned yet, giving the end of its last line break outside of scopes
+    pub(crate) fn scan(&mut self, text: &str, scopes: &[Scope], line_endings: LineEndings) -> Option<usize> {
+        let mut boundary = None;
+        while let Some(end) = self.next(text, scopes, line_endings) {
+            boundary = Some(end);
+        }
+        boundary
+    }
+
+    /// Scans `text` up to the end of its next line break outside of scopes
+    pub(crate) fn next(&mut self, text: &str, scopes: &[Scope], line_endings: LineEndings) -> Option<usize> {
+        while let Some(c) = text[self.scanned..].chars().next() {
+            let start = self.scanned;
+            self.scanned += c.len_utf8();
+            match self.scope {
+                Some(index) => {
+                    let scope = &scopes[index];
+                    if self.escaped {
+                        self.escaped = false;
+                    } else if c == scope.end {
+                        self.scope = None;
+                    } else if c == '\\' && scope.escapes.is_some() {
+                        self.escaped = true;
+                    }
+                },
+                None => {
+                    if let Some(ending) = line_endings.line_break_at(&text[start..]) {
+                        self.scanned = start + ending.as_str().len();
+                        return Some(self.scanned);
+                    } else if let Some(index) = scopes.iter().position(|scope| scope.begin == c) {
+                        self.scope = Some(index);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use crate::{options::{self, Indentation}, tokenizer::{tokenize, tokenize_reader}};
+
+    use super::*;
+
+    const SOURCES: [&str; 4] = [
+        "let x = \"multi\nline \\\" string\";\r\ny = 'é' + 0x1F; // 中文\r\n\n  z(a,\n b)\n",
+        "if a:\n    b = \"\\u{1F600}\"\n    c\r\nd\n",
+        "first\rsecond\u{2028}\"unterminated\n",
+        "no line breaks at all"
+    ];
+
+    fn stream_in_chunks(source: &str, options: &TokenizerOptions, size: usize) -> Vec<Token> {
+        let mut stream = StreamTokenizer::new(options);
+        let mut tokens = vec![];
+        for chunk in source.as_bytes().chunks(size) {
+            tokens.extend(stream.push_bytes(chunk).unwrap());
+        }
+        tokens.extend(stream.finish().unwrap());
+        tokens
+    }
+
+    #[test]
+    fn matches_whole_input_for_any_chunk_size() {
+        let mut indented = options::default();
+        indented.indentation = Some(Indentation::new(4));
+        let mut auto = options::default();
+        auto.line_endings = LineEndings::Auto;
+
+        for options in [options::default(), indented, auto] {
+            for source in SOURCES {
+                let expected = tokenize(source.to_string(), &options);
+                for size in 1..=source.len() {
+                    assert_eq!(stream_in_chunks(source, &options, size), expected, "{:?} in chunks of {}", source, size);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tokenizes_readers() {
+        let source = SOURCES[0];
+        let bytes = format!("\u{FEFF}{}", source).into_bytes();
+        let tokens = tokenize_reader(BufReader::with_capacity(3, bytes.as_slice()), &options::default()).unwrap();
+        assert_eq!(tokens, tokenize(source.to_string(), &options::default()));
+    }
+
+    #[test]
+    fn reports_invalid_utf8_across_chunks() {
+        let options = options::default();
+        let mut stream = StreamTokenizer::new(&options);
+        assert_eq!(stream.push_bytes(b"ab\n\xE4\xB8").unwrap().len(), 2);
+        assert_eq!(stream.push_bytes(b"\xADc\xFF"), Err(DecodeError::new(DecodeErrorKind::InvalidUtf8, 7)));
+
+        let mut stream = StreamTokenizer::new(&options);
+        stream.push_bytes(b"ab\xE4").unwrap();
+        assert_eq!(stream.finish(), Err(DecodeError::new(DecodeErrorKind::InvalidUtf8, 2)));
+    }
+}
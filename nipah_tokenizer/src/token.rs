@@ -1,12 +1,51 @@
-use std::ops::Add;
-
-use crate::{split::SplitItem, options::TokenizerOptions};
+use std::{ops::Add, borrow::Cow};
 
+use crate::{split::{SplitItem, Piece}, escape, options::{TokenizerOptions, ScopeKind, LineEnding}, error::TokenizeErrorKind, number::{self, NumberLiteral, ExactNumber}, interner::{Symbol, Intern}};
 
+/// A piece of the source and what it was read as.
+///
+/// With the `serde` feature a token is a single object: the `kind` of its `TokenType`, the `value` of the kinds that
+/// carry one, then the fields of its `TokenData`, leaving out `number` and `scope` when there are none:
+/// ```json
+/// {"kind":"IntegerLiteral","value":31,"text":"0x1F","position":{"position":8,"line":0},"span":{"start":4,"end":8},"number":{"radix":"Hexadecimal","suffix":null}}
+/// ```
+/// This shape is kept across releases.
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token(pub TokenType, pub TokenData);
 
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct TokenFields<'a> {
+    #[serde(flatten)]
+    kind: &'a TokenType,
+    #[serde(flatten)]
+    data: &'a TokenData
+}
+#[cfg(feature = "serde")]
+impl serde::Serialize for Token {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TokenFields { kind: &self.0, data: &self.1 }.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct OwnedTokenFields {
+    #[serde(flatten)]
+    kind: TokenType,
+    #[serde(flatten)]
+    data: TokenData
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Token {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let fields = OwnedTokenFields::deserialize(deserializer)?;
+        Ok(Token(fields.kind, fields.data))
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(tag = "kind", content = "value"))]
 pub enum TokenType {
     None,
     Any(String),
@@ -33,6 +72,10 @@ pub enum TokenType {
     IntegerLiteral(i64),
     FloatLiteral(f64),
     CharLiteral(char),
+    /// Integer literal kept as written, produced when `TokenizerOptions::exact_numbers` is set
+    ExactIntegerLiteral(ExactNumber),
+    /// Decimal literal kept as written, produced when `TokenizerOptions::exact_numbers` is set
+    ExactDecimalLiteral(ExactNumber),
     /// +
     Plus,
     /// -
@@ -44,11 +87,13 @@ pub enum TokenType {
     /// %
     Modulo,
     Id(String),
+    /// Identifier interned by `tokenize_interned`, produced instead of `Id`
+    Symbol(Symbol),
     /// =
     Equal,
     /// ==
     EqualTo,
-    ///!=
+    /// !=
     NotEqual,
     /// <
     LessThan,
@@ -80,15 +125,73 @@ pub enum TokenType {
     Exclamation,
     /// ?
     QuestionMark,
+    /// Line break, only produced instead of `Newline` when `TokenizerOptions::legacy_eof` is set
     EOF,
-    End
+    #[deprecated(note = "never produced, use `EndOfInput`")]
+    End,
+    /// Line break, or end of a logical line when indentation tracking is enabled
+    Newline(LineEnding),
+    /// Always the last token, unless `TokenizerOptions::end_of_input` is disabled
+    EndOfInput,
+    /// Increase of the indentation level
+    Indent,
+    /// Decrease of the indentation level
+    Dedent,
+    /// A piece of malformed input
+    Error(TokenizeErrorKind)
 }
 
 impl Token {
+    /// Text of the token as written in the source, with quotes and escapes for literals.
+    ///
+    /// Empty for `Symbol` tokens, whose name is kept by the interner they come from.
+    pub fn raw(&self) -> &str {
+        &self.1.text
+    }
+
+    /// Value of a string or char literal, without quotes and with escapes applied
+    pub fn cooked(&self) -> Option<String> {
+        match &self.0 {
+            TokenType::StringLiteral(value) => Some(value.clone()),
+            TokenType::CharLiteral(value) => Some(value.to_string()),
+            _ => None
+        }
+    }
+
     pub fn build(item: SplitItem, options: &TokenizerOptions) -> Token {
-        let text = item.text;
-        let data = TokenData::new(text.clone(), item.position);
-        let mut token_type = match text.as_str() {
+        let piece = Piece {
+            text: Cow::Owned(item.text),
+            position: item.position,
+            span: item.span,
+            error: item.error,
+            scope: item.scope,
+            closed: item.scope.is_none() || item.cooked.is_some()
+        };
+        Token::from_piece_cooked(piece, item.cooked, options, None)
+    }
+
+    pub(crate) fn from_piece(piece: Piece, options: &TokenizerOptions, interner: Option<&mut (dyn Intern + '_)>) -> Token {
+        Token::from_piece_cooked(piece, None, options, interner)
+    }
+
+    /// Builds the token of `piece`, taking `cooked` as the value of a literal if given, or working it out otherwise.
+    ///
+    /// With an `interner`, identifiers become `Symbol` tokens, which keep no text of their own.
+    fn from_piece_cooked(piece: Piece, cooked: Option<String>, options: &TokenizerOptions, interner: Option<&mut (dyn Intern + '_)>) -> Token {
+        let mut data = TokenData::new(String::new(), piece.position);
+        data.span = piece.span;
+        data.scope = piece.scope;
+        if let Some(kind) = piece.error {
+            data.text = piece.text.into_owned();
+            return Token(TokenType::Error(kind), data);
+        }
+        let text: &str = &piece.text;
+        if let Some(ending) = LineEnding::at(text).filter(|ending| ending.as_str() == text) {
+            let kind = if options.legacy_eof { TokenType::EOF } else { TokenType::Newline(ending) };
+            data.text = piece.text.into_owned();
+            return Token(kind, data);
+        }
+        let mut token_type = match text {
             "@" => TokenType::AtSign,
             "//" => TokenType::SingleLineComment,
             "/*" => TokenType::BeginMultilineComment,
@@ -126,57 +229,78 @@ impl Token {
             "!" => TokenType::Exclamation,
             "?" => TokenType::QuestionMark,
             "," => TokenType::Comma,
-            "\n" | "\r" => TokenType::EOF,
             _ => TokenType::None
         };
         if token_type == TokenType::None {
-            if data.text.starts_with('"') && data.text.ends_with('"') {
-                let mut fstr = data.text.to_owned();
-                fstr.remove(0);
-                fstr.remove(fstr.len() - 1);
-                token_type = TokenType::StringLiteral(fstr);
-            } else if (options.try_id)(data.text.as_str()) {
-                token_type = TokenType::Id(data.text.to_owned());
-            } else if let Ok(integer) = &data.text.parse::<i64>() {
-                token_type = TokenType::IntegerLiteral(*integer);
-            } else if data.text.ends_with('f') { 
-                    if let Ok(float) = data.text[..data.text.len() - 1].parse::<f64>() {
-                        token_type = TokenType::FloatLiteral(float);
-                    } else { }
-            } else if data.text.contains('.') {
-                if let Ok(float) = data.text.parse::<f64>() {
-                    token_type = TokenType::FloatLiteral(float);
-                } else if let Ok(pcr) = data.text.parse::<char>() {
-                    token_type = TokenType::CharLiteral(pcr);
-                } else { }
-            } else if data.text.starts_with('\'') && data.text.ends_with('\'') {
-                let mut fcr = data.text.to_owned();
-                fcr.remove(0);
-                fcr.remove(fcr.len() - 1);
-                if let Ok(pcr) = fcr.parse::<char>() {
-                    token_type = TokenType::CharLiteral(pcr);
-                } else { }
+            let scope = match piece.scope {
+                Some(id) => options.scopes.iter().find(|scope| scope.id == id),
+                None => options.scopes.iter().find(|scope| text.len() >= scope.begin.len_utf8() + scope.end.len_utf8()
+                    && text.starts_with(scope.begin) && text.ends_with(scope.end))
+            };
+            if let (Some(scope), true) = (scope, piece.closed) {
+                let value = cooked.unwrap_or_else(|| {
+                    let content = &text[scope.begin.len_utf8()..text.len() - scope.end.len_utf8()];
+                    escape::cook(content, scope.escapes.as_ref()).into_owned()
+                });
+                token_type = match scope.kind {
+                    ScopeKind::String => TokenType::StringLiteral(value),
+                    ScopeKind::Char => {
+                        let mut chars = value.chars();
+                        match (chars.next(), chars.next()) {
+                            (Some(c), None) => TokenType::CharLiteral(c),
+                            (None, _) => TokenType::Error(TokenizeErrorKind::EmptyCharLiteral),
+                            _ => TokenType::Error(TokenizeErrorKind::MultiCharLiteral)
+                        }
+                    }
+                };
+            } else if (options.try_id)(text) {
+                match interner {
+                    Some(interner) => return Token(TokenType::Symbol(interner.intern(text)), data),
+                    None => token_type = TokenType::Id(text.to_owned())
+                }
+            } else if number::is_number_start(text) {
+                match number::parse_number(text, options.exact_numbers) {
+                    Ok((number_type, literal)) => {
+                        token_type = number_type;
+                        data.number = Some(literal);
+                    },
+                    Err(kind) => token_type = TokenType::Error(kind)
+                }
             }
-        } else {  };
+        }
+        data.text = piece.text.into_owned();
         Token(token_type, data)
     }
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TokenData {
     pub text: String,
-    pub position: TokenPosition
+    pub position: TokenPosition,
+    /// Bytes of the source the token was read from
+    pub span: Span,
+    /// Radix and suffix of numeric literals
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub number: Option<NumberLiteral>,
+    /// Id of the `Scope` string and char literals were read in
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub scope: Option<i64>
 }
 impl TokenData {
     pub fn new(text: String, position: TokenPosition) -> TokenData {
         TokenData {
             text,
-            position
+            position,
+            span: Span::default(),
+            number: None,
+            scope: None
         }
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TokenPosition {
     pub position: i32,
     pub line: i32,
@@ -199,6 +323,38 @@ impl Add for TokenPosition {
     }
 }
 
+/// A range of byte offsets into the source
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize
+}
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span {
+            start,
+            end
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Span covering both this span and the other one
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::options::default;
@@ -253,4 +409,80 @@ mod tests {
         assert_eq!(token.1.position.position, 1);
         assert_eq!(token.1.position.line, 2);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_build_char_literal_matrix() {
+        let options = default();
+        let build = |text: &str| crate::tokenizer::tokenize(text.to_string(), &options).remove(0).0;
+
+        assert_eq!(build("'a'"), TokenType::CharLiteral('a'));
+        assert_eq!(build("'é'"), TokenType::CharLiteral('é'));
+        assert_eq!(build("'😀'"), TokenType::CharLiteral('😀'));
+        assert_eq!(build(r"'\n'"), TokenType::CharLiteral('\n'));
+        assert_eq!(build(r"'\''"), TokenType::CharLiteral('\''));
+        assert_eq!(build(r"'\\'"), TokenType::CharLiteral('\\'));
+        assert_eq!(build(r"'\0'"), TokenType::CharLiteral('\0'));
+        assert_eq!(build(r"'\x41'"), TokenType::CharLiteral('A'));
+        assert_eq!(build(r"'\u{1F600}'"), TokenType::CharLiteral('😀'));
+        assert_eq!(build(r"'\uD83D\uDE00'"), TokenType::CharLiteral('😀'));
+        assert_eq!(build("'\"'"), TokenType::CharLiteral('"'));
+
+        assert_eq!(build("''"), TokenType::Error(TokenizeErrorKind::EmptyCharLiteral));
+        assert_eq!(build("'hello'"), TokenType::Error(TokenizeErrorKind::MultiCharLiteral));
+        assert_eq!(build(r"'\n\n'"), TokenType::Error(TokenizeErrorKind::MultiCharLiteral));
+        assert_eq!(build("'e\u{301}'"), TokenType::Error(TokenizeErrorKind::MultiCharLiteral));
+    }
+
+    #[test]
+    fn test_build_dot_is_not_a_char_literal() {
+        let item = SplitItem::new(".".to_owned(), TokenPosition::new(0, 0));
+        assert_eq!(Token::build(item, &default()).0, TokenType::Dot);
+        let item = SplitItem::new("a.b".to_owned(), TokenPosition::new(0, 0));
+        assert_eq!(Token::build(item, &default()).0, TokenType::Id("a.b".to_owned()));
+        let item = SplitItem::new("_.".to_owned(), TokenPosition::new(0, 0));
+        assert_eq!(Token::build(item, &default()).0, TokenType::None);
+    }
+
+    #[test]
+    fn test_build_string_literal_in_single_quotes_with_string_scope() {
+        let mut options = default();
+        options.scopes[1].kind = ScopeKind::String;
+        let token = crate::tokenizer::tokenize("'hello'".to_string(), &options).remove(0);
+        assert_eq!(token.0, TokenType::StringLiteral("hello".to_owned()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_shape_is_stable() {
+        let tokens = crate::tokenizer::tokenize("x = 0x1F \"a\" ''\n".to_string(), &default());
+        assert_eq!(serde_json::to_string(&tokens).unwrap(), concat!(
+            "[",
+            r#"{"kind":"Id","value":"x","text":"x","position":{"position":1,"line":0},"span":{"start":0,"end":1}},"#,
+            r#"{"kind":"Equal","text":"=","position":{"position":2,"line":0},"span":{"start":2,"end":3}},"#,
+            r#"{"kind":"IntegerLiteral","value":31,"text":"0x1F","position":{"position":8,"line":0},"span":{"start":4,"end":8},"number":{"radix":"Hexadecimal","suffix":null}},"#,
+            r#"{"kind":"StringLiteral","value":"a","text":"\"a\"","position":{"position":10,"line":0},"span":{"start":9,"end":12},"scope":0},"#,
+            r#"{"kind":"Error","value":"EmptyCharLiteral","text":"''","position":{"position":13,"line":0},"span":{"start":13,"end":15},"scope":1},"#,
+            r#"{"kind":"Newline","value":"Lf","text":"\n","position":{"position":15,"line":0},"span":{"start":15,"end":16}},"#,
+            r#"{"kind":"EndOfInput","text":"","position":{"position":0,"line":1},"span":{"start":16,"end":16}}"#,
+            "]"
+        ));
+        assert_eq!(serde_json::to_string(&TokenType::Comma).unwrap(), r#"{"kind":"Comma"}"#);
+        assert_eq!(serde_json::to_string(&TokenType::Error(TokenizeErrorKind::Io(std::io::ErrorKind::NotFound))).unwrap(), r#"{"kind":"Error","value":{"Io":"NotFound"}}"#);
+        assert_eq!(serde_json::to_string(&TokenType::Newline(LineEnding::CrLf)).unwrap(), r#"{"kind":"Newline","value":"CrLf"}"#);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_round_trip() {
+        let source = "let x = \"multi\nline \\\" string\";\r\ny = 'é' + 0x1F_u8 * -2.5e3 99999999999999999999;\n  '' \"unterminated\n";
+        let mut exact = default();
+        exact.exact_numbers = true;
+        for options in [default(), crate::options::legacy(), exact] {
+            let tokens = crate::tokenizer::tokenize(source.to_string(), &options);
+            let json = serde_json::to_string(&tokens).unwrap();
+            assert_eq!(serde_json::from_str::<Vec<Token>>(&json).unwrap(), tokens);
+        }
+        let tokens = crate::tokenizer::tokenize_interned(source.to_string(), &default(), &mut crate::interner::Interner::new());
+        assert_eq!(serde_json::from_value::<Vec<Token>>(serde_json::to_value(&tokens).unwrap()).unwrap(), tokens);
+    }
+}
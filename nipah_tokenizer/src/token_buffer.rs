@@ -0,0 +1,503 @@
+use std::mem::{size_of, size_of_val};
+
+use crate::{token::{Token, TokenType, TokenData, TokenPosition, Span}, options::LineEnding, error::TokenizeErrorKind, number::{NumberLiteral, ExactNumber}, interner::Symbol};
+
+/// Marks a token without a payload in `TokenBuffer::payloads`
+const NO_PAYLOAD: u32 = u32::MAX;
+
+macro_rules! token_kinds {
+    (plain: $($plain:ident),* ; payload: $($payload:ident),*) => {
+        /// The variant of a `TokenType` without its payload, one byte each.
+        ///
+        /// The byte is also the tag of the token in `binary` encodings, whose version goes up when the bytes change.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        #[repr(u8)]
+        pub enum TokenKind {
+            $($plain,)*
+            $($payload,)*
+        }
+        impl TokenKind {
+            /// Every kind, in the order of their bytes
+            pub const ALL: &'static [TokenKind] = &[$(TokenKind::$plain,)* $(TokenKind::$payload,)*];
+        }
+
+        #[allow(deprecated)]
+        fn plain_kind(kind: &TokenType) -> Option<TokenKind> {
+            match kind {
+                $(TokenType::$plain => Some(TokenKind::$plain),)*
+                _ => None
+            }
+        }
+
+        #[allow(deprecated)]
+        pub(crate) fn plain_type(kind: TokenKind) -> Option<TokenType> {
+            match kind {
+                $(TokenKind::$plain => Some(TokenType::$plain),)*
+                _ => None
+            }
+        }
+    };
+}
+
+token_kinds! {
+    plain: None, AtSign, SingleLineComment, BeginMultilineComment, EndMultilineComment, Comma, OpenParenthesis,
+        CloseParenthesis, OpenCurlyBrace, CloseCurlyBrace, OpenBracket, CloseBracket, OpenSquareBracket, TrueLiteral,
+        FalseLiteral, NullLiteral, Plus, Minus, Divide, Multiply, Modulo, Equal, EqualTo, NotEqual, LessThan,
+        LessThanOrEqual, GreaterThan, GreaterThanOrEqual, Colon, Semicolon, And, Or, DollarSign, Arrow, FatArrow, Dot,
+        HashSign, Exclamation, QuestionMark, EOF, End, EndOfInput, Indent, Dedent;
+    payload: Any, StringLiteral, IntegerLiteral, FloatLiteral, CharLiteral, ExactIntegerLiteral, ExactDecimalLiteral,
+        Id, Symbol, Newline, Error
+}
+
+impl TokenKind {
+    pub fn of(kind: &TokenType) -> TokenKind {
+        plain_kind(kind).unwrap_or_else(|| match kind {
+            TokenType::Any(_) => TokenKind::Any,
+            TokenType::StringLiteral(_) => TokenKind::StringLiteral,
+            TokenType::IntegerLiteral(_) => TokenKind::IntegerLiteral,
+            TokenType::FloatLiteral(_) => TokenKind::FloatLiteral,
+            TokenType::CharLiteral(_) => TokenKind::CharLiteral,
+            TokenType::ExactIntegerLiteral(_) => TokenKind::ExactIntegerLiteral,
+            TokenType::ExactDecimalLiteral(_) => TokenKind::ExactDecimalLiteral,
+            TokenType::Id(_) => TokenKind::Id,
+            TokenType::Symbol(_) => TokenKind::Symbol,
+            TokenType::Newline(_) => TokenKind::Newline,
+            TokenType::Error(_) => TokenKind::Error,
+            _ => unreachable!("{:?} has no payload", kind)
+        })
+    }
+}
+impl From<&TokenType> for TokenKind {
+    fn from(kind: &TokenType) -> Self {
+        TokenKind::of(kind)
+    }
+}
+
+/// Value carried by a `TokenType` variant, strings being kept in the text of the buffer
+#[derive(Debug, Clone)]
+enum Payload {
+    Text(Span),
+    Integer(i64),
+    Float(f64),
+    Char(char),
+    /// Boxed as it is many times larger than the other payloads
+    Exact(Box<ExactNumber>),
+    Symbol(Symbol),
+    LineEnding(LineEnding),
+    Error(TokenizeErrorKind)
+}
+
+/// Fields of `TokenData` most tokens leave empty, kept apart so the others take no room for them
+#[derive(Debug, Clone)]
+struct Extra {
+    index: usize,
+    number: Option<NumberLiteral>,
+    scope: Option<i64>
+}
+
+/// Tokens stored column by column: one array of kinds, one of spans and so on, all texts sharing one string.
+///
+/// Takes a fraction of the memory of a `Vec<Token>` and keeps the kinds packed together for scanning.
+#[derive(Debug, Clone, Default)]
+pub struct TokenBuffer {
+    kinds: Vec<TokenKind>,
+    spans: Vec<Span>,
+    positions: Vec<TokenPosition>,
+    /// Range of `text` holding the raw text of each token
+    texts: Vec<Span>,
+    /// Index into `values` of the payload of each token, `NO_PAYLOAD` for tokens without one
+    payloads: Vec<u32>,
+    values: Vec<Payload>,
+    /// Sorted by token index
+    extras: Vec<Extra>,
+    text: String
+}
+impl TokenBuffer {
+    pub fn new() -> Self {
+        TokenBuffer::default()
+    }
+
+    pub fn with_capacity(tokens: usize) -> Self {
+        TokenBuffer {
+            kinds: Vec::with_capacity(tokens),
+            spans: Vec::with_capacity(tokens),
+            positions: Vec::with_capacity(tokens),
+            texts: Vec::with_capacity(tokens),
+            payloads: Vec::with_capacity(tokens),
+            ..TokenBuffer::default()
+        }
+    }
+
+    /// Appends a copy of `token`
+    pub fn push(&mut self, token: &Token) {
+        let Token(kind, data) = token;
+        let index = self.kinds.len();
+        let text = self.push_text(&data.text);
+
+        let payload = match kind {
+            // Identifiers and most `Any` are their raw text, which is then stored once
+            TokenType::Any(value) | TokenType::StringLiteral(value) | TokenType::Id(value) => Some(Payload::Text(
+                if *value == data.text { text } else { self.push_text(value) }
+            )),
+            TokenType::IntegerLiteral(value) => Some(Payload::Integer(*value)),
+            TokenType::FloatLiteral(value) => Some(Payload::Float(*value)),
+            TokenType::CharLiteral(value) => Some(Payload::Char(*value)),
+            TokenType::ExactIntegerLiteral(value) | TokenType::ExactDecimalLiteral(value) => Some(Payload::Exact(Box::new(value.clone()))),
+            TokenType::Symbol(value) => Some(Payload::Symbol(*value)),
+            TokenType::Newline(value) => Some(Payload::LineEnding(*value)),
+            TokenType::Error(value) => Some(Payload::Error(value.clone())),
+            _ => None
+        };
+        let payload = match payload {
+            Some(payload) => {
+                self.values.push(payload);
+                u32::try_from(self.values.len() - 1).expect("more than u32::MAX tokens with payloads")
+            },
+            None => NO_PAYLOAD
+        };
+
+        self.kinds.push(TokenKind::of(kind));
+        self.spans.push(data.span);
+        self.positions.push(data.position);
+        self.texts.push(text);
+        self.payloads.push(payload);
+        if data.number.is_some() || data.scope.is_some() {
+            self.extras.push(Extra { index, number: data.number.clone(), scope: data.scope });
+        }
+    }
+
+    fn push_text(&mut self, text: &str) -> Span {
+        let start = self.text.len();
+        self.text.push_str(text);
+        Span::new(start, self.text.len())
+    }
+
+    pub fn len(&self) -> usize {
+        self.kinds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty()
+    }
+
+    /// The kind of every token, in order
+    pub fn kinds(&self) -> &[TokenKind] {
+        &self.kinds
+    }
+
+    /// The span of every token, in order
+    pub fn spans(&self) -> &[Span] {
+        &self.spans
+    }
+
+    /// The position of every token, in order
+    pub fn positions(&self) -> &[TokenPosition] {
+        &self.positions
+    }
+
+    pub fn get(&self, index: usize) -> Option<TokenRef<'_>> {
+        (index < self.len()).then_some(TokenRef { buffer: self, index })
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = TokenRef<'_>> + ExactSizeIterator {
+        (0..self.len()).map(|index| TokenRef { buffer: self, index })
+    }
+
+    /// A cursor before the first token
+    pub fn cursor(&self) -> Cursor<'_> {
+        Cursor { buffer: self, index: 0 }
+    }
+
+    pub fn to_tokens(&self) -> Vec<Token> {
+        self.iter().map(|token| token.to_token()).collect()
+    }
+
+    /// Bytes of heap the buffer holds, counting the capacity of every array
+    pub fn heap_size(&self) -> usize {
+        self.kinds.capacity() * size_of::<TokenKind>()
+            + self.spans.capacity() * size_of::<Span>()
+            + self.positions.capacity() * size_of::<TokenPosition>()
+            + self.texts.capacity() * size_of::<Span>()
+            + self.payloads.capacity() * size_of::<u32>()
+            + self.values.capacity() * size_of::<Payload>()
+            + self.values.iter().map(|value| match value {
+                Payload::Exact(number) => size_of::<ExactNumber>() + number.digits().len(),
+                _ => 0
+            }).sum::<usize>()
+            + self.extras.capacity() * size_of::<Extra>()
+            + self.extras.iter().map(|extra| extra.number.as_ref().map_or(0, number_heap_size)).sum::<usize>()
+            + self.text.capacity()
+    }
+
+    /// Bytes of heap the buffer holds for each of its tokens
+    pub fn bytes_per_token(&self) -> f64 {
+        self.heap_size() as f64 / self.len().max(1) as f64
+    }
+
+    /// Drops the spare capacity of every array
+    pub fn shrink_to_fit(&mut self) {
+        self.kinds.shrink_to_fit();
+        self.spans.shrink_to_fit();
+        self.positions.shrink_to_fit();
+        self.texts.shrink_to_fit();
+        self.payloads.shrink_to_fit();
+        self.values.shrink_to_fit();
+        self.extras.shrink_to_fit();
+        self.text.shrink_to_fit();
+    }
+
+    fn slice(&self, span: Span) -> &str {
+        &self.text[span.start..span.end]
+    }
+
+    fn extra(&self, index: usize) -> Option<&Extra> {
+        self.extras.binary_search_by_key(&index, |extra| extra.index).ok().map(|found| &self.extras[found])
+    }
+
+    fn payload(&self, index: usize) -> Option<&Payload> {
+        match self.payloads[index] {
+            NO_PAYLOAD => None,
+            payload => Some(&self.values[payload as usize])
+        }
+    }
+}
+
+/// Bytes of heap a `Vec<Token>` holding `tokens` takes, to compare with `TokenBuffer::heap_size`
+pub fn tokens_heap_size(tokens: &[Token]) -> usize {
+    size_of_val(tokens) + tokens.iter().map(|Token(kind, data)| {
+        let payload = match kind {
+            TokenType::Any(value) | TokenType::StringLiteral(value) | TokenType::Id(value) => value.capacity(),
+            TokenType::ExactIntegerLiteral(value) | TokenType::ExactDecimalLiteral(value) => value.digits().len(),
+            _ => 0
+        };
+        payload + data.text.capacity() + data.number.as_ref().map_or(0, number_heap_size)
+    }).sum::<usize>()
+}
+
+fn number_heap_size(number: &NumberLiteral) -> usize {
+    number.suffix.as_ref().map_or(0, String::capacity)
+}
+
+impl From<&[Token]> for TokenBuffer {
+    fn from(tokens: &[Token]) -> Self {
+        let mut buffer = TokenBuffer::with_capacity(tokens.len());
+        for token in tokens {
+            buffer.push(token);
+        }
+        buffer
+    }
+}
+impl From<Vec<Token>> for TokenBuffer {
+    fn from(tokens: Vec<Token>) -> Self {
+        TokenBuffer::from(tokens.as_slice())
+    }
+}
+impl From<&TokenBuffer> for Vec<Token> {
+    fn from(buffer: &TokenBuffer) -> Self {
+        buffer.to_tokens()
+    }
+}
+impl From<TokenBuffer> for Vec<Token> {
+    fn from(buffer: TokenBuffer) -> Self {
+        buffer.to_tokens()
+    }
+}
+impl FromIterator<Token> for TokenBuffer {
+    fn from_iter<I: IntoIterator<Item = Token>>(tokens: I) -> Self {
+        let mut buffer = TokenBuffer::new();
+        for token in tokens {
+            buffer.push(&token);
+        }
+        buffer
+    }
+}
+
+/// One token of a `TokenBuffer`, read from its arrays without copying
+#[derive(Debug, Clone, Copy)]
+pub struct TokenRef<'b> {
+    buffer: &'b TokenBuffer,
+    index: usize
+}
+impl<'b> TokenRef<'b> {
+    /// Place of the token in its buffer
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn kind(&self) -> TokenKind {
+        self.buffer.kinds[self.index]
+    }
+
+    pub fn span(&self) -> Span {
+        self.buffer.spans[self.index]
+    }
+
+    pub fn position(&self) -> TokenPosition {
+        self.buffer.positions[self.index]
+    }
+
+    /// Text of the token as written in the source, like `Token::raw`
+    pub fn raw(&self) -> &'b str {
+        self.buffer.slice(self.buffer.texts[self.index])
+    }
+
+    /// The string carried by `Any`, `StringLiteral` and `Id` tokens
+    pub fn str_value(&self) -> Option<&'b str> {
+        match self.buffer.payload(self.index) {
+            Some(Payload::Text(text)) => Some(self.buffer.slice(*text)),
+            _ => None
+        }
+    }
+
+    pub fn number(&self) -> Option<&'b NumberLiteral> {
+        self.buffer.extra(self.index).and_then(|extra| extra.number.as_ref())
+    }
+
+    pub fn scope(&self) -> Option<i64> {
+        self.buffer.extra(self.index).and_then(|extra| extra.scope)
+    }
+
+    /// The full type of the token, payload included
+    pub fn token_type(&self) -> TokenType {
+        let kind = self.kind();
+        if let Some(plain) = plain_type(kind) {
+            return plain;
+        }
+        match (kind, self.buffer.payload(self.index).expect("token kind with a payload stored without one")) {
+            (TokenKind::Any, Payload::Text(_)) => TokenType::Any(self.str_value().unwrap().to_string()),
+            (TokenKind::StringLiteral, Payload::Text(_)) => TokenType::StringLiteral(self.str_value().unwrap().to_string()),
+            (TokenKind::Id, Payload::Text(_)) => TokenType::Id(self.str_value().unwrap().to_string()),
+            (TokenKind::IntegerLiteral, Payload::Integer(value)) => TokenType::IntegerLiteral(*value),
+            (TokenKind::FloatLiteral, Payload::Float(value)) => TokenType::FloatLiteral(*value),
+            (TokenKind::CharLiteral, Payload::Char(value)) => TokenType::CharLiteral(*value),
+            (TokenKind::ExactIntegerLiteral, Payload::Exact(value)) => TokenType::ExactIntegerLiteral((**value).clone()),
+            (TokenKind::ExactDecimalLiteral, Payload::Exact(value)) => TokenType::ExactDecimalLiteral((**value).clone()),
+            (TokenKind::Symbol, Payload::Symbol(value)) => TokenType::Symbol(*value),
+            (TokenKind::Newline, Payload::LineEnding(value)) => TokenType::Newline(*value),
+            (TokenKind::Error, Payload::Error(value)) => TokenType::Error(value.clone()),
+            (kind, payload) => unreachable!("{:?} stored with {:?}", kind, payload)
+        }
+    }
+
+    /// A `Token` equal to the one pushed into the buffer
+    pub fn to_token(&self) -> Token {
+        let extra = self.buffer.extra(self.index);
+        Token(self.token_type(), TokenData {
+            text: self.raw().to_string(),
+            position: self.position(),
+            span: self.span(),
+            number: extra.and_then(|extra| extra.number.clone()),
+            scope: extra.and_then(|extra| extra.scope)
+        })
+    }
+}
+
+/// Walks a `TokenBuffer` forwards and backwards, looking ahead without moving
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor<'b> {
+    buffer: &'b TokenBuffer,
+    index: usize
+}
+impl<'b> Cursor<'b> {
+    /// Index of the token `next` gives
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Moves before the token at `index`, or to the end when past it
+    pub fn seek(&mut self, index: usize) {
+        self.index = index.min(self.buffer.len());
+    }
+
+    /// The token `next` gives, without moving
+    pub fn peek(&self) -> Option<TokenRef<'b>> {
+        self.peek_nth(0)
+    }
+
+    /// The token `n` places after the one `next` gives, without moving
+    pub fn peek_nth(&self, n: usize) -> Option<TokenRef<'b>> {
+        self.buffer.get(self.index.checked_add(n)?)
+    }
+
+    /// Moves back one token, giving it
+    pub fn prev(&mut self) -> Option<TokenRef<'b>> {
+        self.index = self.index.checked_sub(1)?;
+        self.buffer.get(self.index)
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.index >= self.buffer.len()
+    }
+}
+impl<'b> Iterator for Cursor<'b> {
+    type Item = TokenRef<'b>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let token = self.buffer.get(self.index)?;
+        self.index += 1;
+        Some(token)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.buffer.len() - self.index;
+        (left, Some(left))
+    }
+}
+impl ExactSizeIterator for Cursor<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{options::{self, Indentation}, tokenizer::{tokenize, tokenize_interned}, interner::Interner};
+
+    use super::*;
+
+    const SOURCE: &str = "let x = \"multi\nline \\\" string\";\r\ny = 'é' + 0x1F_u8 * 2.5; // 中文\r\n\n  z(a,\n b) != null\n  \"unterminated\n";
+
+    #[test]
+    fn converts_to_and_from_tokens() {
+        let mut indented = options::default();
+        indented.indentation = Some(Indentation::new(2));
+        let mut exact = options::default();
+        exact.exact_numbers = true;
+
+        for options in [options::default(), options::legacy(), indented, exact] {
+            let tokens = tokenize(SOURCE.to_string(), &options);
+            let buffer = TokenBuffer::from(tokens.as_slice());
+            assert_eq!(buffer.len(), tokens.len());
+            assert_eq!(buffer.to_tokens(), tokens);
+            assert!(buffer.iter().zip(&tokens).all(|(token, expected)| token.kind() == TokenKind::of(&expected.0)));
+        }
+
+        let mut interner = Interner::new();
+        let tokens = tokenize_interned(SOURCE.to_string(), &options::default(), &mut interner);
+        assert_eq!(tokens.iter().cloned().collect::<TokenBuffer>().to_tokens(), tokens);
+    }
+
+    #[test]
+    fn cursor_moves_both_ways() {
+        let buffer = TokenBuffer::from(tokenize("a + b".to_string(), &options::default()));
+        let mut cursor = buffer.cursor();
+        assert_eq!(cursor.peek_nth(2).map(|token| token.str_value()), Some(Some("b")));
+        assert_eq!(cursor.next().map(|token| token.raw()), Some("a"));
+        assert_eq!(cursor.peek().map(|token| token.kind()), Some(TokenKind::Plus));
+        cursor.seek(usize::MAX);
+        assert!(cursor.is_at_end());
+        assert_eq!(cursor.prev().map(|token| token.kind()), Some(TokenKind::EndOfInput));
+        assert_eq!(cursor.len(), 1);
+        cursor.seek(0);
+        assert_eq!(cursor.prev().map(|token| token.index()), None);
+        assert_eq!(buffer.get(1).map(|token| token.span()), Some(Span::new(2, 3)));
+        assert!(buffer.get(buffer.len()).is_none());
+    }
+
+    #[test]
+    fn takes_less_memory_than_a_token_vec() {
+        let source = SOURCE.repeat(100);
+        let tokens = tokenize(source, &options::default());
+        let mut buffer = TokenBuffer::from(tokens.as_slice());
+        buffer.shrink_to_fit();
+        let per_token = tokens_heap_size(&tokens) as f64 / tokens.len() as f64;
+        assert!(buffer.bytes_per_token() * 2.0 < per_token, "{} bytes per token against {}", buffer.bytes_per_token(), per_token);
+    }
+}
@@ -0,0 +1,127 @@
+use std::{collections::VecDeque, pin::Pin, task::{Context, Poll}};
+
+use futures_core::Stream;
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::{options::TokenizerOptions, stream::StreamTokenizer, token::{Token, TokenType, TokenPosition, Span}, error::{TokenizeError, TokenizeErrorKind, DecodeError}};
+
+const BUFFER_SIZE: usize = 8 * 1024;
+
+/// Tokens of UTF-8 text read from an `AsyncRead`, given as soon as the lines holding them arrive.
+///
+/// Malformed input comes out as an error in place of its `Error` token, and the stream goes on after it.
+/// Failing to read or decode the input ends the stream.
+pub struct TokenStream<'o, R> {
+    reader: R,
+    /// `None` once the input is over
+    tokenizer: Option<StreamTokenizer<'o>>,
+    ready: VecDeque<Token>,
+    buffer: Box<[u8]>
+}
+impl<'o, R: AsyncRead + Unpin> TokenStream<'o, R> {
+    pub fn new(reader: R, options: &'o TokenizerOptions) -> Self {
+        TokenStream {
+            reader,
+            tokenizer: Some(StreamTokenizer::new(options)),
+            ready: VecDeque::new(),
+            buffer: vec![0; BUFFER_SIZE].into_boxed_slice()
+        }
+    }
+}
+impl<R: AsyncRead + Unpin> Stream for TokenStream<'_, R> {
+    type Item = Result<Token, TokenizeError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(token) = this.ready.pop_front() {
+                return Poll::Ready(Some(match &token.0 {
+                    TokenType::Error(kind) => Err(TokenizeError::new(kind.clone(), token.1.position, token.1.span)),
+                    _ => Ok(token)
+                }));
+            }
+            let Some(tokenizer) = this.tokenizer.as_mut() else {
+                return Poll::Ready(None);
+            };
+
+            let mut buffer = ReadBuf::new(&mut this.buffer);
+            let result = match Pin::new(&mut this.reader).poll_read(cx, &mut buffer) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(err)) => {
+                    this.tokenizer = None;
+                    let kind = TokenizeErrorKind::Io(err.kind());
+                    return Poll::Ready(Some(Err(TokenizeError::new(kind, TokenPosition::default(), Span::default()))));
+                },
+                Poll::Ready(Ok(())) if buffer.filled().is_empty() => this.tokenizer.take().unwrap().finish(),
+                Poll::Ready(Ok(())) => tokenizer.push_bytes(buffer.filled())
+            };
+            match result {
+                Ok(tokens) => this.ready.extend(tokens),
+                Err(err) => {
+                    this.tokenizer = None;
+                    return Poll::Ready(Some(Err(decode_error(err))));
+                }
+            }
+        }
+    }
+}
+
+fn decode_error(err: DecodeError) -> TokenizeError {
+    TokenizeError::new(TokenizeErrorKind::InvalidEncoding(err.kind), TokenPosition::default(), Span::new(err.offset, err.offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use tokio::{io::{duplex, AsyncWriteExt}, sync::oneshot};
+
+    use crate::{options, tokenizer::tokenize, error::DecodeErrorKind};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn tokenizes_as_bytes_arrive() {
+        let source = "let s = \"two\nlines\";\r\nlet c = 'é'; x(\"\\u{1F600}\")\n";
+        let first_line = source.find("\r\n").unwrap() + 2;
+        let options = options::default();
+        let (mut writer, reader) = duplex(4);
+        let (resume, resumed) = oneshot::channel();
+        let writing = tokio::spawn(async move {
+            for chunk in source.as_bytes()[..first_line].chunks(3) {
+                writer.write_all(chunk).await.unwrap();
+            }
+            resumed.await.unwrap();
+            for chunk in source.as_bytes()[first_line..].chunks(3) {
+                writer.write_all(chunk).await.unwrap();
+            }
+        });
+
+        // The first line comes out while the writer still holds back the rest
+        let mut stream = TokenStream::new(reader, &options);
+        let mut tokens = vec![];
+        while tokens.len() < 6 {
+            tokens.push(stream.next().await.unwrap().unwrap());
+        }
+        resume.send(()).unwrap();
+        while let Some(token) = stream.next().await {
+            tokens.push(token.unwrap());
+        }
+        writing.await.unwrap();
+        assert_eq!(tokens, tokenize(source.to_string(), &options));
+    }
+
+    #[tokio::test]
+    async fn reports_malformed_input_and_goes_on() {
+        let options = options::default();
+        let mut stream = TokenStream::new(&b"a = 'xy'\nb"[..], &options);
+        let results: Vec<_> = (&mut stream).collect().await;
+        assert_eq!(results[2].as_ref().unwrap_err().kind, TokenizeErrorKind::MultiCharLiteral);
+        assert_eq!(results[4].as_ref().unwrap().0, TokenType::Id("b".to_string()));
+
+        let mut stream = TokenStream::new(&b"a\n\xFF"[..], &options);
+        let results: Vec<_> = (&mut stream).collect().await;
+        let err = results.last().unwrap().as_ref().unwrap_err();
+        assert_eq!(err.kind, TokenizeErrorKind::InvalidEncoding(DecodeErrorKind::InvalidUtf8));
+        assert_eq!(err.span.start, 2);
+    }
+}
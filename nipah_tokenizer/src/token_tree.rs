@@ -0,0 +1,246 @@
+use crate::{token::{Token, TokenType, Span}, error::DelimiterError};
+
+/// The pairs of tokens groups are made of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Delimiter {
+    /// `(` and `)`
+    Parenthesis,
+    /// `{` and `}`
+    CurlyBrace,
+    /// `[` and `]`
+    Bracket
+}
+impl Delimiter {
+    /// The delimiter `kind` opens, if any
+    pub fn opened_by(kind: &TokenType) -> Option<Delimiter> {
+        match kind {
+            TokenType::OpenParenthesis => Some(Delimiter::Parenthesis),
+            TokenType::OpenCurlyBrace => Some(Delimiter::CurlyBrace),
+            TokenType::OpenBracket => Some(Delimiter::Bracket),
+            _ => None
+        }
+    }
+
+    /// The delimiter `kind` closes, if any
+    pub fn closed_by(kind: &TokenType) -> Option<Delimiter> {
+        match kind {
+            TokenType::CloseParenthesis => Some(Delimiter::Parenthesis),
+            TokenType::CloseCurlyBrace => Some(Delimiter::CurlyBrace),
+            TokenType::CloseBracket => Some(Delimiter::Bracket),
+            _ => None
+        }
+    }
+
+    pub fn open_str(self) -> &'static str {
+        match self {
+            Delimiter::Parenthesis => "(",
+            Delimiter::CurlyBrace => "{",
+            Delimiter::Bracket => "["
+        }
+    }
+
+    pub fn close_str(self) -> &'static str {
+        match self {
+            Delimiter::Parenthesis => ")",
+            Delimiter::CurlyBrace => "}",
+            Delimiter::Bracket => "]"
+        }
+    }
+}
+
+/// Tokens between a pair of delimiters, the delimiters themselves left out
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    pub delimiter: Delimiter,
+    pub children: Vec<TokenTree>,
+    pub open_span: Span,
+    /// Span of the closing delimiter, empty where it was missing in trees built with `build_recovering`
+    pub close_span: Span
+}
+impl Group {
+    /// From the start of the opening delimiter to the end of the closing one
+    pub fn span(&self) -> Span {
+        Span::new(self.open_span.start, self.close_span.end.max(self.open_span.end))
+    }
+}
+
+/// A token, or a group of them between matching delimiters
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenTree {
+    Token(Token),
+    Group(Group)
+}
+impl TokenTree {
+    pub fn span(&self) -> Span {
+        match self {
+            TokenTree::Token(token) => token.1.span,
+            TokenTree::Group(group) => group.span()
+        }
+    }
+}
+
+/// Groups `tokens` into trees by their delimiters, failing on the first ones that do not pair up
+pub fn build(tokens: impl IntoIterator<Item = Token>) -> Result<Vec<TokenTree>, DelimiterError> {
+    let (trees, errors) = build_recovering(tokens);
+    match errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(trees)
+    }
+}
+
+/// Groups `tokens` into trees by their delimiters, going on past the ones that do not pair up.
+///
+/// A closing delimiter that matches an enclosing group closes every group inside it, which are then reported as
+/// unclosed or mismatched. One that matches no group is kept as a token. Groups still open at `EndOfInput`, or at
+/// the end of the tokens, end there with an empty `close_span`.
+pub fn build_recovering(tokens: impl IntoIterator<Item = Token>) -> (Vec<TokenTree>, Vec<DelimiterError>) {
+    let mut builder = Builder::default();
+    for token in tokens {
+        builder.push(token);
+    }
+    builder.finish()
+}
+
+#[derive(Default)]
+struct Builder {
+    /// Groups still open, innermost last, each with the trees before it
+    open: Vec<(Group, Vec<TokenTree>)>,
+    trees: Vec<TokenTree>,
+    errors: Vec<DelimiterError>,
+    /// Empty span after the last token, where unclosed groups end
+    end: Span
+}
+impl Builder {
+    fn push(&mut self, token: Token) {
+        let span = token.1.span;
+        self.end = Span::new(span.end, span.end);
+
+        if let Some(delimiter) = Delimiter::opened_by(&token.0) {
+            let group = Group { delimiter, children: vec![], open_span: span, close_span: span };
+            let outer = std::mem::take(&mut self.trees);
+            self.open.push((group, outer));
+            return;
+        }
+        if let Some(delimiter) = Delimiter::closed_by(&token.0) {
+            self.close(delimiter, token);
+            return;
+        }
+        if token.0 == TokenType::EndOfInput {
+            self.close_all(Span::new(span.start, span.start));
+        }
+        self.trees.push(TokenTree::Token(token));
+    }
+
+    fn close(&mut self, delimiter: Delimiter, token: Token) {
+        let close_span = token.1.span;
+        let Some(depth) = self.open.iter().rposition(|(group, _)| group.delimiter == delimiter) else {
+            self.errors.push(match self.open.last() {
+                Some((group, _)) => DelimiterError::Mismatched { open: group.delimiter, open_span: group.open_span, close: delimiter, close_span },
+                None => DelimiterError::Unopened { delimiter, close_span }
+            });
+            self.trees.push(TokenTree::Token(token));
+            return;
+        };
+
+        let end = Span::new(close_span.start, close_span.start);
+        let innermost = self.open.len() - 1;
+        for level in (depth + 1..=innermost).rev() {
+            let group = &self.open[level].0;
+            self.errors.push(if level == innermost {
+                DelimiterError::Mismatched { open: group.delimiter, open_span: group.open_span, close: delimiter, close_span }
+            } else {
+                DelimiterError::Unclosed { delimiter: group.delimiter, open_span: group.open_span, end }
+            });
+            self.pop(end);
+        }
+        self.pop(close_span);
+    }
+
+    /// Ends the innermost group with `close_span`, putting it in the one around it
+    fn pop(&mut self, close_span: Span) {
+        let (mut group, outer) = self.open.pop().unwrap();
+        group.children = std::mem::replace(&mut self.trees, outer);
+        group.close_span = close_span;
+        self.trees.push(TokenTree::Group(group));
+    }
+
+    /// Ends every group still open at `end`, reporting them from the outermost
+    fn close_all(&mut self, end: Span) {
+        let first = self.errors.len();
+        while let Some((group, _)) = self.open.last() {
+            self.errors.push(DelimiterError::Unclosed { delimiter: group.delimiter, open_span: group.open_span, end });
+            self.pop(end);
+        }
+        self.errors[first..].reverse();
+    }
+
+    fn finish(mut self) -> (Vec<TokenTree>, Vec<DelimiterError>) {
+        self.close_all(self.end);
+        (self.trees, self.errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{options, tokenizer::tokenize};
+
+    use super::*;
+
+    fn trees(source: &str) -> (Vec<TokenTree>, Vec<DelimiterError>) {
+        build_recovering(tokenize(source.to_string(), &options::default()))
+    }
+
+    /// The trees written back as delimiters and raw texts, `?` standing for a missing delimiter
+    fn show(trees: &[TokenTree]) -> String {
+        trees.iter().map(|tree| match tree {
+            TokenTree::Token(token) => token.raw().to_string(),
+            TokenTree::Group(group) => {
+                let close = if group.close_span.is_empty() { "?" } else { group.delimiter.close_str() };
+                format!("{}{}{}", group.delimiter.open_str(), show(&group.children), close)
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn nests_groups() {
+        let tokens = tokenize("f(a, [b{c}]) {}".to_string(), &options::default());
+        let built = build(tokens).unwrap();
+        assert_eq!(show(&built), "f(a,[b{c}]){}");
+
+        let TokenTree::Group(call) = &built[1] else { panic!("{:?}", built[1]) };
+        assert_eq!(call.delimiter, Delimiter::Parenthesis);
+        assert_eq!((call.open_span, call.close_span), (Span::new(1, 2), Span::new(11, 12)));
+        assert_eq!(call.span(), Span::new(1, 12));
+        assert_eq!(call.children.len(), 3);
+        assert!(matches!(built.last(), Some(TokenTree::Token(Token(TokenType::EndOfInput, _)))));
+    }
+
+    #[test]
+    fn reports_unbalanced_delimiters() {
+        let (built, errors) = trees("a) (b [c");
+        assert_eq!(show(&built), "a)(b[c??");
+        assert_eq!(errors, vec![
+            DelimiterError::Unopened { delimiter: Delimiter::Parenthesis, close_span: Span::new(1, 2) },
+            DelimiterError::Unclosed { delimiter: Delimiter::Parenthesis, open_span: Span::new(3, 4), end: Span::new(8, 8) },
+            DelimiterError::Unclosed { delimiter: Delimiter::Bracket, open_span: Span::new(6, 7), end: Span::new(8, 8) }
+        ]);
+        assert_eq!(errors[1].to_string(), "unclosed delimiter `(` opened at byte 3");
+    }
+
+    #[test]
+    fn reports_mismatched_delimiters_with_both_spans() {
+        let (built, errors) = trees("(a [b {c) d]");
+        assert_eq!(show(&built), "(a[b{c??)d]");
+        assert_eq!(errors, vec![
+            DelimiterError::Mismatched { open: Delimiter::CurlyBrace, open_span: Span::new(6, 7), close: Delimiter::Parenthesis, close_span: Span::new(8, 9) },
+            DelimiterError::Unclosed { delimiter: Delimiter::Bracket, open_span: Span::new(3, 4), end: Span::new(8, 8) },
+            DelimiterError::Unopened { delimiter: Delimiter::Bracket, close_span: Span::new(11, 12) }
+        ]);
+        assert_eq!(errors[0].to_string(), "mismatched closing delimiter `)` at byte 8 for `{` opened at byte 6");
+
+        let (built, errors) = trees("(]");
+        assert_eq!(show(&built), "(]?");
+        assert_eq!(errors[0], DelimiterError::Mismatched { open: Delimiter::Parenthesis, open_span: Span::new(0, 1), close: Delimiter::Bracket, close_span: Span::new(1, 2) });
+        assert_eq!(build(tokenize("(]".to_string(), &options::default())), Err(errors[0]));
+    }
+}
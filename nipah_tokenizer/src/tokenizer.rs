@@ -1,232 +1,492 @@
-use std::str::Chars;
+use std::{str::Chars, path::Path, fs, io::{self, BufRead}, borrow::Cow};
 
-use crate::{options::{TokenizerOptions, IncludeMode, Scope, EndOfLine, SplitAggregator, SplitAggregatorFn}, split::SplitItem, token::{TokenPosition, Token}};
+use crate::{options::{TokenizerOptions, IncludeMode, Scope, Escapes, LineEnding, LineEndings, SplitAggregator, SplitAggregatorFn}, split::{SplitItem, Piece}, scan::StopBytes, token::{TokenPosition, Token, TokenType, TokenData, Span}, error::{TokenizeError, TokenizeErrorKind, DecodeError}, encoding::{self, Encoding}, stream::StreamTokenizer, interner::Intern, indentation, number, escape};
 
 
 pub fn tokenize(entry: String, options: &TokenizerOptions) -> Vec<Token> {
-    let pieces = split_string(entry, options);
-    let mut tokens = vec![];
+    tokenize_str(&entry, options)
+}
+
+pub(crate) fn tokenize_str(entry: &str, options: &TokenizerOptions) -> Vec<Token> {
+    tokenize_with(entry, options, None)
+}
+
+/// Tokenizes `entry`, interning identifiers into `interner` if given
+fn tokenize_with(entry: &str, options: &TokenizerOptions, mut interner: Option<&mut dyn Intern>) -> Vec<Token> {
+    let line_endings = options.line_ending_policy().resolve(entry);
+    let lines = options.indentation.as_ref()
+        .map(|indentation| indentation::measure_lines(entry, indentation, line_endings));
+
+    let mut pieces = vec![];
+    split_str(entry, line_endings, options, &mut pieces);
+    let mut tokens = Vec::with_capacity(pieces.len() + 1);
     for piece in pieces {
-        let token = Token::build(piece, options);
+        let token = Token::from_piece(piece, options, interner.as_deref_mut());
         tokens.push(token);
     }
+
+    if let Some(lines) = lines {
+        tokens = indentation::apply_indentation(tokens, &lines);
+    }
+    if options.end_of_input {
+        tokens.push(end_of_input(entry, line_endings, 0, 0));
+    }
     tokens
 }
 
-type StringBuilder = Vec<char>;
-trait StringBuilderPush {
-    fn push_string(&mut self, text: &str) -> StringBuilder;
+/// Tokenizes as `tokenize`, but with identifiers interned into `interner` as `Symbol` tokens instead of `Id`.
+///
+/// Passing the same interner for many inputs gives the same symbol to the same name in all of them.
+/// `Symbol` tokens keep no text, their name is in the interner and their span points at it in the source.
+pub fn tokenize_interned(entry: String, options: &TokenizerOptions, mut interner: impl Intern) -> Vec<Token> {
+    tokenize_with(&entry, options, Some(&mut interner))
 }
-impl StringBuilderPush for StringBuilder {
-    fn push_string(&mut self, text: &str) -> StringBuilder {
-        self.extend(text.chars());
-        self.clone()
+
+/// Tokens of a piece of a longer input, starting at byte `offset` and at the start of line `first_line` of it
+pub(crate) fn tokenize_piece(piece: &str, line_endings: LineEndings, options: &TokenizerOptions, offset: usize, first_line: usize) -> Vec<Token> {
+    let mut pieces = vec![];
+    split_str(piece, line_endings, options, &mut pieces);
+    let mut tokens = Vec::with_capacity(pieces.len());
+    for item in pieces {
+        let mut token = Token::from_piece(item, options, None);
+        token.1.position.line += first_line as i32;
+        token.1.span = Span::new(token.1.span.start + offset, token.1.span.end + offset);
+        tokens.push(token);
     }
+    tokens
 }
 
-pub fn split_string(text: String, options: &TokenizerOptions) -> Vec<SplitItem> {
-    let mut list = vec![];
-    
-    let mut position = 0_i32;
-    let mut line = 0_i32;
+/// The `EndOfInput` token after `rest`, the end of a text that starts at `offset` on line `first_line`
+pub(crate) fn end_of_input(rest: &str, line_endings: LineEndings, first_line: usize, offset: usize) -> Token {
+    let lines = line_endings.lines(rest);
+    let (start, end) = lines[lines.len() - 1];
+    let position = TokenPosition::new(rest[start..end].chars().count() as i32, (first_line + lines.len() - 1) as i32);
+    let mut data = TokenData::new(String::new(), position);
+    data.span = Span::new(offset + rest.len(), offset + rest.len());
+    Token(TokenType::EndOfInput, data)
+}
+
+/// Same as `tokenize`, but fails with the first malformed piece of input instead of producing `Error` tokens
+pub fn try_tokenize(entry: String, options: &TokenizerOptions) -> Result<Vec<Token>, TokenizeError> {
+    let tokens = tokenize(entry, options);
+    for token in &tokens {
+        if let TokenType::Error(kind) = &token.0 {
+            return Err(TokenizeError::new(kind.clone(), token.1.position, token.1.span));
+        }
+    }
+    Ok(tokens)
+}
 
-    split_string_normal_mode(text, &mut position, &mut line, options, &mut list);
+/// Tokenizes raw bytes, such as the contents of a file.
+///
+/// A byte order mark selects UTF-8, UTF-16 LE or UTF-16 BE and is skipped, otherwise the bytes must be UTF-8.
+/// Spans of the tokens are offsets in the decoded text.
+pub fn tokenize_bytes(bytes: &[u8], options: &TokenizerOptions) -> Result<Vec<Token>, DecodeError> {
+    tokenize_bytes_as(bytes, Encoding::Utf8, options)
+}
 
-    list.retain(|f| !f.text.is_empty());
+/// Same as `tokenize_bytes`, but decoding with `encoding` when there is no byte order mark
+pub fn tokenize_bytes_as(bytes: &[u8], encoding: Encoding, options: &TokenizerOptions) -> Result<Vec<Token>, DecodeError> {
+    Ok(tokenize(encoding::decode(bytes, encoding)?, options))
+}
 
-    let mut any_changed: bool;
+/// Reads and tokenizes a file as `tokenize_bytes` does, failing with `ErrorKind::InvalidData` when it cannot be decoded
+pub fn tokenize_file(path: impl AsRef<Path>, options: &TokenizerOptions) -> io::Result<Vec<Token>> {
+    let bytes = fs::read(path)?;
+    tokenize_bytes(&bytes, options).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Tokenizes UTF-8 text from a reader a buffer at a time, without holding all of the text in memory
+pub fn tokenize_reader<R: BufRead>(mut reader: R, options: &TokenizerOptions) -> io::Result<Vec<Token>> {
+    let invalid_data = |err| io::Error::new(io::ErrorKind::InvalidData, err);
+    let mut stream = StreamTokenizer::new(options);
+    let mut tokens = vec![];
     loop {
-        (list, any_changed) = apply_aggregators(list.as_slice(), options.split_aggregators.as_slice());
-        if !any_changed {
+        let buffer = match reader.fill_buf() {
+            Ok(buffer) => buffer,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err)
+        };
+        if buffer.is_empty() {
             break;
         }
+        let len = buffer.len();
+        tokens.extend(stream.push_bytes(buffer).map_err(invalid_data)?);
+        reader.consume(len);
     }
+    tokens.extend(stream.finish().map_err(invalid_data)?);
+    Ok(tokens)
+}
+
+pub fn split_string(text: String, options: &TokenizerOptions) -> Vec<SplitItem> {
+    split_pieces(&text, options).into_iter().map(|piece| piece.into_item(options)).collect()
+}
+
+/// Splits `text` into pieces that borrow from it, only aggregations across blank space own their text
+pub fn split_pieces<'s>(text: &'s str, options: &TokenizerOptions) -> Vec<Piece<'s>> {
+    let mut pieces = vec![];
+    split_pieces_into(text, options, &mut pieces);
+    pieces
+}
+
+/// Splits `text` into `pieces`, which are cleared first, so a buffer can be reused from one text to the next
+pub fn split_pieces_into<'s>(text: &'s str, options: &TokenizerOptions, pieces: &mut Vec<Piece<'s>>) {
+    pieces.clear();
+    split_str(text, options.line_ending_policy().resolve(text), options, pieces);
+}
+
+/// Splits with an already resolved line ending policy, so pieces of a longer input split the same as the whole
+pub(crate) fn split_str<'s>(text: &'s str, line_endings: LineEndings, options: &TokenizerOptions, list: &mut Vec<Piece<'s>>) {
+    split_scanning(text, line_endings, options, list, Some(StopBytes::new(options)));
+}
+
+/// Splits looking at every character by itself when `stops` is `None`, or skipping over runs of plain bytes otherwise
+fn split_scanning<'s>(text: &'s str, line_endings: LineEndings, options: &TokenizerOptions, list: &mut Vec<Piece<'s>>, stops: Option<StopBytes>) {
+    let mut position = 0_i32;
+    let mut line = 0_i32;
+
+    split_string_normal_mode(text, &mut position, &mut line, line_endings, options, stops, list);
+
+    list.retain(|f| !f.text.is_empty());
 
-    list
+    while apply_aggregators(text, list, &options.split_aggregators) {}
 }
 
-fn apply_aggregators(mut inputs: &[SplitItem], aggregators: &[SplitAggregator]) -> (Vec<SplitItem>, bool) {
+/// Merges the pieces matched by aggregators in place, telling whether any matched
+fn apply_aggregators<'s>(text: &'s str, list: &mut Vec<Piece<'s>>, aggregators: &[SplitAggregator]) -> bool {
     let mut changed_any = false;
-    let mut outputs = vec![];
-    let mut carry = StringBuilder::new();
-
-    while !inputs.is_empty() {
-        let mut is_match = false;
-        for aggregator in aggregators {
-            carry.clear();
-            if apply_aggregator(inputs, &aggregator.detectors, &mut carry, &mut outputs, None) {
-                inputs = &inputs[aggregator.detectors.len()..];
-                is_match = true;
+    let (mut read, mut write) = (0, 0);
+
+    while read < list.len() {
+        let matched = aggregators.iter()
+            .map(|aggregator| aggregator.detectors.as_slice())
+            .find(|detectors| aggregator_matches(&list[read..], detectors));
+        match matched {
+            Some(detectors) => {
+                list[write] = merge(text, &list[read..read + detectors.len()]);
+                read += detectors.len();
                 changed_any = true;
-                break;
+            },
+            None => {
+                list.swap(write, read);
+                read += 1;
             }
         }
-        if !is_match {
-            outputs.push(inputs[0].clone());
-            inputs = &inputs[1..];
-        }
+        write += 1;
     }
-    (outputs, changed_any)
+    list.truncate(write);
+    changed_any
 }
-fn apply_aggregator(inputs: &[SplitItem], aggregator: &[SplitAggregatorFn], carry: &mut StringBuilder, outputs: &mut Vec<SplitItem>, last_matched: Option<&SplitItem>) -> bool {
-    let inp_len = inputs.len();
-    let agr_len = aggregator.len();
-
-    if agr_len == 0 {
-        if !carry.is_empty() {
-            outputs.push(SplitItem::new(carry.iter().collect(),
-             if let Some(lm) = last_matched { lm.position } else { TokenPosition::new(0, 0) }));
-        }
-        true
-    }
-    else if agr_len == 0 && inp_len == 0 {
-        return true;
-    }
-    else if inp_len >= agr_len && aggregator[0](&inputs[0].text) {
-        return if !aggregator.is_empty() {
-            apply_aggregator(&inputs[1..], &aggregator[1..], &mut carry.push_string(&inputs[0].text), outputs, Some(&inputs[0]))
-        } else {
-            outputs.push(SplitItem::new(carry.iter().collect(),
-             if let Some(lm) = last_matched { lm.position } else { TokenPosition::new(0, 0) }));
-            true
-        }
+
+fn aggregator_matches(inputs: &[Piece], detectors: &[SplitAggregatorFn]) -> bool {
+    !detectors.is_empty() && inputs.len() >= detectors.len()
+        && inputs.iter().zip(detectors).all(|(input, detector)| input.error.is_none() && detector(&input.text))
+}
+
+/// A single piece made of `pieces`, borrowing from `text` when they follow each other in it
+fn merge<'s>(text: &'s str, pieces: &[Piece<'s>]) -> Piece<'s> {
+    let (first, last) = (&pieces[0], &pieces[pieces.len() - 1]);
+    let span = first.span.to(last.span);
+    let contiguous = pieces.windows(2).all(|pair| pair[0].span.end == pair[1].span.start)
+        && pieces.iter().all(|piece| matches!(piece.text, Cow::Borrowed(_)));
+    let merged = if contiguous {
+        Cow::Borrowed(&text[span.start..span.end])
     } else {
-        false
+        Cow::Owned(pieces.iter().map(|piece| piece.text.as_ref()).collect())
+    };
+    Piece {
+        text: merged,
+        ..Piece::new("", last.position.position, last.position.line, span)
+    }
+}
+
+/// Byte offset in `text` of the next character `chars` will give
+fn offset_of(text: &str, chars: &Chars) -> usize {
+    text.len() - chars.as_str().len()
+}
+
+/// Pushes the word between `start` and `end` unless it is empty
+fn push_word<'s>(text: &'s str, start: usize, end: usize, position: i32, line: i32, list: &mut Vec<Piece<'s>>) {
+    if start < end {
+        list.push(Piece::new(&text[start..end], position, line, Span::new(start, end)));
     }
 }
 
-fn split_string_normal_mode(text: String, position: &mut i32, line: &mut i32, options: &TokenizerOptions, list: &mut Vec<SplitItem>) {
+#[allow(clippy::too_many_arguments)]
+fn split_string_normal_mode<'s>(text: &'s str, position: &mut i32, line: &mut i32, line_endings: LineEndings, options: &TokenizerOptions, stops: Option<StopBytes>, list: &mut Vec<Piece<'s>>) {
     let separators = &options.separators;
     let scopes = &options.scopes;
-    let eofs = &options.end_of_lines;
 
     let chars = &mut text.chars();
 
-    let mut current = StringBuilder::new();
+    // The word being read runs from here up to the current character
+    let mut word_start = 0;
 
-    while let Some(c) = &chars.next() {
-        current.push(*c);
-
-        // Check for separators
-        for sep in separators {
-            let is_match = sep.cmatch == *c;
-            if is_match {
-                match sep.include {
-                    IncludeMode::Aggregate => {
-                        let item = SplitItem::new_pl(current.iter().collect(), *position, *line);
-                        list.push(item);
-                        current.clear();
-                    },
-                    IncludeMode::Separate => {
-                        let item = SplitItem::new_pl(
-                            if current.is_empty() { "".to_string() } else { current[..current.len() - 1].iter().collect() },
-                            *position, *line);
-                        let sep_item = SplitItem::new_pl(sep.cmatch.to_string(), *position, *line);
-                        list.push(item);
-                        list.push(sep_item);
-                        current.clear();
-                    },
-                    IncludeMode::None => {
-                        let item = SplitItem::new_pl(
-                            if current.is_empty() { "".to_string() } else { current[..current.len() - 1].iter().collect() },
-                            *position, *line);
-                        list.push(item);
-                        current.clear();
-                    }
+    loop {
+        // Plain ASCII joins the word being read as it is, so whole runs of it are skipped at once
+        if let Some(stops) = stops {
+            let at = offset_of(text, chars);
+            if at > word_start || !text.as_bytes().get(at).is_some_and(u8::is_ascii_digit) {
+                let run = stops.plain_len(&text.as_bytes()[at..]);
+                if run > 0 {
+                    *chars = text[at + run..].chars();
+                    *position += run as i32;
                 }
-                break;
             }
         }
 
-        // Check for scopes
-        for scope in scopes {
-            if scope.begin == *c {
-                current.clear();
-                split_string_scoped_mode(chars, position, line, options, list, scope);
-                break;
+        let Some(c) = &chars.next() else {
+            break;
+        };
+        let c_end = offset_of(text, chars);
+        let c_start = c_end - c.len_utf8();
+
+        // Numeric literals are scanned as a whole, so their dots, exponent signs and suffixes stay in one piece
+        if word_start == c_start && c.is_ascii_digit() {
+            let end = c_start + number::scan_number(&text[c_start..]);
+            split_string_number_mode(&text[c_start..end], c_start, chars, position, line, list);
+            attach_sign(text, list);
+            word_start = end;
+            continue;
+        }
+
+        // Line breaks become a single piece each, even when written as `\r\n`
+        if let Some(ending) = line_endings.line_break_at(&text[c_start..]) {
+            push_word(text, word_start, c_start, *position, *line, list);
+            let end = c_start + ending.as_str().len();
+            list.push(Piece::new(&text[c_start..end], *position, *line, Span::new(c_start, end)));
+            while offset_of(text, chars) < end {
+                chars.next();
             }
+            word_start = end;
+            *position = 0;
+            *line += 1;
+            continue;
+        }
+        // Line break characters the policy does not accept are only blank space
+        if LineEnding::at(&text[c_start..]).is_some() {
+            push_word(text, word_start, c_start, *position, *line, list);
+            word_start = c_end;
+            *position += 1;
+            continue;
         }
 
-        // Check for escaping
-        if *c == '\\' {
-            current.pop();
-            split_string_escaped_mode(chars, position, line, options, &mut current);
+        // Check for separators
+        if let Some(sep) = separators.iter().find(|sep| sep.cmatch == *c) {
+            match sep.include {
+                IncludeMode::Aggregate => push_word(text, word_start, c_end, *position, *line, list),
+                IncludeMode::Separate => {
+                    push_word(text, word_start, c_start, *position, *line, list);
+                    list.push(Piece::new(&text[c_start..c_end], *position, *line, Span::new(c_start, c_end)));
+                },
+                IncludeMode::None => push_word(text, word_start, c_start, *position, *line, list)
+            }
+            word_start = c_end;
         }
 
-        process_position_and_eof(*c, position, line, eofs);
+        // Check for scopes
+        if let Some(scope) = scopes.iter().find(|scope| scope.begin == *c) {
+            push_word(text, word_start, c_start, *position, *line, list);
+            split_string_scoped_mode(text, c_start, chars, position, line, line_endings, list, scope);
+            word_start = offset_of(text, chars);
+        }
+
+        *position += 1;
     }
-    if !current.is_empty() {
-        let item = SplitItem::new_pl(current.iter().collect(), *position, *line);
-        list.push(item);
+    push_word(text, word_start, text.len(), *position, *line, list);
+}
+
+fn split_string_number_mode<'s>(literal: &'s str, start: usize, chars: &mut Chars, position: &mut i32, line: &mut i32, list: &mut Vec<Piece<'s>>) {
+    for _ in literal.chars().skip(1) {
+        chars.next();
     }
+    *position += literal.chars().count() as i32;
+    list.push(Piece::new(literal, *position, *line, Span::new(start, start + literal.len())));
 }
 
-fn split_string_scoped_mode(chars: &mut Chars, position: &mut i32, line: &mut i32, options: &TokenizerOptions, list: &mut Vec<SplitItem>, scope: &Scope) {
-    let eofs = &options.end_of_lines;
+/// Makes a `-` right before the number just read its sign, unless it follows an operand and so subtracts
+fn attach_sign<'s>(text: &'s str, list: &mut Vec<Piece<'s>>) {
+    let count = list.len();
+    if count < 2 {
+        return;
+    }
+    let (minus, number) = (&list[count - 2], &list[count - 1]);
+    if minus.text != "-" || minus.span.end != number.span.start {
+        return;
+    }
+    if count > 2 && ends_operand(&list[count - 3]) {
+        return;
+    }
+    let span = minus.span.to(number.span);
+    let position = number.position;
+    list.truncate(count - 2);
+    list.push(Piece::new(&text[span.start..span.end], position.position, position.line, span));
+}
 
-    let mut current = StringBuilder::new();
+/// Whether a piece can be the last one of an operand, such as an identifier, a literal or a closing parenthesis
+fn ends_operand(piece: &Piece) -> bool {
+    piece.scope.is_some() || matches!(piece.text.as_ref(), ")" | "]")
+        || piece.text.starts_with(|c: char| c.is_alphanumeric() || c == '_')
+}
 
-    current.push(scope.begin);
-    
+#[allow(clippy::too_many_arguments)]
+fn split_string_scoped_mode<'s>(text: &'s str, start: usize, chars: &mut Chars, position: &mut i32, line: &mut i32, line_endings: LineEndings, list: &mut Vec<Piece<'s>>, scope: &Scope) {
+    // The first invalid escape makes the whole literal an error
+    let mut error = None;
     while let Some(c) = chars.next() {
-        current.push(c);
-
         // Check for the end of scope
         if scope.end == c {
-            let item = SplitItem::new_pl(current.iter().collect(), *position, *line);
+            let end = offset_of(text, chars);
+            let mut item = Piece::new(&text[start..end], *position, *line, Span::new(start, end));
+            item.scope = Some(scope.id);
+            item.error = error;
             list.push(item);
-            process_position_and_eof(c, position, line, eofs);
-            current.clear();
+            *position += 1;
             return;
         }
 
         // Check for escaping
-        if c == '\\' {
-            current.pop();
-            split_string_escaped_mode(chars, position, line, options, &mut current);
+        if let (Some(escapes), '\\') = (&scope.escapes, c) {
+            *position += 1;
+            if let Err(kind) = split_string_escaped_mode(text, chars, position, line, line_endings, escapes) {
+                error = error.or(Some(kind));
+            }
+            continue;
         }
 
-        process_position_and_eof(c, position, line, eofs);
-    }
-    if !current.is_empty() {
-        let item = SplitItem::new_pl(current.iter().collect(), *position, *line);
-        list.push(item);
-    }
-}
-fn split_string_escaped_mode<'c>(chars: &'c mut Chars, position: &mut i32, line: &mut i32, options: &TokenizerOptions, current: &mut Vec<char>) {
-    if let Some(c) = chars.next() {
-        if c == 'n' {
-            current.push('\n');
-        } else if c == 'r' {
-            current.push('\r');
-        } else if c == 't' {
-            current.push('\t');
+        let c_start = offset_of(text, chars) - c.len_utf8();
+        if let Some(ending) = line_endings.line_break_at(&text[c_start..]) {
+            for _ in ending.as_str().chars().skip(1) {
+                chars.next();
+            }
+            *position = 0;
+            *line += 1;
         } else {
-            current.push(c);
+            *position += 1;
         }
-        process_position_and_eof(c, position, line, &options.end_of_lines);
-    } else {
-        // Should panic here or handle errors somehow
     }
+
+    // The scope was never closed, so the piece has no value of its own
+    let mut item = Piece::new(&text[start..], *position, *line, Span::new(start, text.len()));
+    item.scope = Some(scope.id);
+    item.closed = false;
+    item.error = Some(TokenizeErrorKind::UnterminatedLiteral);
+    list.push(item);
 }
 
-fn process_position_and_eof(c: char, position: &mut i32, line: &mut i32, eofs: &Vec<EndOfLine>) {
-    *position += 1;
-    for eof in eofs {
-        if eof.eof == c {
-            *position = 0;
-            *line += 1;
-            break;
+/// Checks one escape sequence, its value is only worked out when the literal is cooked
+fn split_string_escaped_mode(text: &str, chars: &mut Chars, position: &mut i32, line: &mut i32, line_endings: LineEndings, escapes: &Escapes) -> Result<(), TokenizeErrorKind> {
+    let start = offset_of(text, chars);
+    let result = escape::unescape(chars, escapes);
+    advance_position(&text[start..offset_of(text, chars)], position, line, line_endings);
+    result.map(|_| ())
+}
+
+/// Moves the position past `text`, counting each line break once
+fn advance_position(text: &str, position: &mut i32, line: &mut i32, line_endings: LineEndings) {
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        match line_endings.line_break_at(rest) {
+            Some(ending) => {
+                *position = 0;
+                *line += 1;
+                rest = &rest[ending.as_str().len()..];
+            },
+            None => {
+                *position += 1;
+                rest = &rest[c.len_utf8()..];
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::token::TokenType;
+    use std::{alloc::{GlobalAlloc, Layout, System}, cell::Cell};
 
     use super::*;
 
+    /// Counts allocations per thread, so tests running at the same time do not add to each other's count
+    struct CountingAllocator;
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.realloc(ptr, layout, new_size)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn count_allocations(f: impl FnOnce()) -> usize {
+        let before = ALLOCATIONS.with(Cell::get);
+        f();
+        ALLOCATIONS.with(Cell::get) - before
+    }
+
+    #[test]
+    fn splitting_only_allocates_the_piece_list() {
+        let options = crate::options::default();
+        let text = "let x = \"a\\n b\" + 'c'; // y == -5 >= 0x1F && f(2.5)\r\n".repeat(200);
+
+        let mut pieces = vec![];
+        let first = count_allocations(|| split_pieces_into(&text, &options, &mut pieces));
+        assert!(pieces.len() > 3000);
+        assert!(first < 20, "{} allocations for {} pieces", first, pieces.len());
+
+        // With a buffer that is already large enough nothing is allocated at all
+        assert_eq!(count_allocations(|| split_pieces_into(&text, &options, &mut pieces)), 0);
+        assert!(pieces.iter().all(|piece| matches!(piece.text, Cow::Borrowed(_))));
+
+        // Pieces aggregated across blank space are the only ones owning their text
+        split_pieces_into("a = = b", &options, &mut pieces);
+        assert_eq!(pieces[1].text, Cow::<str>::Owned("==".to_string()));
+    }
+
+    #[test]
+    fn block_scanning_splits_like_scalar_scanning() {
+        let mut aggregate = crate::options::default();
+        aggregate.separators[0].include = IncludeMode::Aggregate;
+        aggregate.separators[2].include = IncludeMode::None;
+        let mut unicode = crate::options::default();
+        unicode.separators.push(crate::options::default().separators[0].clone());
+        unicode.separators.last_mut().unwrap().cmatch = 'é';
+        unicode.line_endings = LineEndings::Cr;
+
+        let fragments = [
+            "identifier", "with_digits_123", "a", " ", "\t", "\n", "\r\n", "\r", "\"str \\\" ing\"", "'c'", "\\", "42", "0x1F",
+            "2.5e3", ".", "-", "=", "*", "(", ")", "é中", "\u{2028}", "%", "longwordlongwordlongword"
+        ];
+        let mut state = 0x2545F4914F6CDD1D_u64;
+        for options in [crate::options::default(), aggregate, unicode] {
+            for _ in 0..3000 {
+                let mut text = String::new();
+                for _ in 0..state % 30 {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    text.push_str(fragments[state as usize % fragments.len()]);
+                }
+                let line_endings = options.line_ending_policy().resolve(&text);
+                let (mut blocks, mut scalar) = (vec![], vec![]);
+                split_scanning(&text, line_endings, &options, &mut blocks, Some(StopBytes::new(&options)));
+                split_scanning(&text, line_endings, &options, &mut scalar, None);
+                assert_eq!(blocks, scalar, "{:?}", text);
+            }
+        }
+    }
+
     #[test]
     fn test_split_string() {
         let text = "Hello World!".to_string();
@@ -283,17 +543,259 @@ mod tests {
         assert_eq!(split[2].text, "=>");
     }
 
+    #[test]
+    fn test_split_with_string_detectors() {
+        let mut options = crate::options::default();
+        options.split_aggregators.push(SplitAggregator::new(vec![
+            SplitAggregator::string_detector(|x: &String| x == ":"),
+            SplitAggregator::string_detector(|x: &String| x == ":")
+        ]));
+        let pieces: Vec<crate::Piece> = split_pieces("a::b", &options);
+        let texts: Vec<&str> = pieces.iter().map(|piece| piece.text.as_ref()).collect();
+        assert_eq!(texts, vec!["a", "::", "b"]);
+    }
+
+    #[test]
+    fn test_split_numeric_literals() {
+        let text = "x = 6.02e23 + 1e-9 * 0xFF - 2.5f32".to_string();
+        let split = split_string(text, &crate::options::default());
+        let texts: Vec<&str> = split.iter().map(|item| item.text.as_str()).collect();
+        assert_eq!(texts, vec!["x", "=", "6.02e23", "+", "1e-9", "*", "0xFF", "-", "2.5f32"]);
+    }
+
+    #[test]
+    fn test_split_signs_only_numbers_that_are_not_subtracted() {
+        let texts = |text: &str| split_string(text.to_string(), &crate::options::default())
+            .into_iter().map(|item| item.text).collect::<Vec<_>>();
+        assert_eq!(texts("x = -1"), vec!["x", "=", "-1"]);
+        assert_eq!(texts("f(-2, - 3)"), vec!["f", "(", "-2", ",", "-", "3", ")"]);
+        assert_eq!(texts("x - 2"), vec!["x", "-", "2"]);
+        assert_eq!(texts("x -2"), vec!["x", "-", "2"]);
+        assert_eq!(texts("f(a)-1 - -1"), vec!["f", "(", "a", ")", "-", "1", "-", "-1"]);
+        assert_eq!(texts("\"s\"-1"), vec!["\"s\"", "-", "1"]);
+    }
+
+    #[test]
+    fn test_split_string_spans() {
+        let text = "let x = \"a b\";".to_string();
+        let split = split_string(text, &crate::options::default());
+        let spans: Vec<(usize, usize)> = split.iter().map(|item| (item.span.start, item.span.end)).collect();
+        assert_eq!(spans, vec![(0, 3), (4, 5), (6, 7), (8, 13), (13, 14)]);
+    }
+
+    #[test]
+    fn test_split_string_unicode_escapes() {
+        let text = r#""\u{1F600} \u00e9 \x41 \0""#.to_string();
+        let split = split_string(text, &crate::options::default());
+        assert_eq!(split.len(), 1);
+        assert_eq!(split[0].text, r#""\u{1F600} \u00e9 \x41 \0""#);
+        assert_eq!(split[0].cooked.as_deref(), Some("😀 é A \0"));
+    }
+
+    #[test]
+    fn test_split_string_escapes_only_inside_scopes() {
+        let text = r"a\nb".to_string();
+        let split = split_string(text, &crate::options::default());
+        assert_eq!(split.len(), 1);
+        assert_eq!(split[0].text, r"a\nb");
+    }
+
+    #[test]
+    fn test_split_string_raw_scope() {
+        let mut options = crate::options::default();
+        options.scopes[0].escapes = None;
+        let split = split_string(r#""C:\new\""#.to_string(), &options);
+        assert_eq!(split[0].text, r#""C:\new\""#);
+    }
+
     // Tokenizer
     #[test]
     fn can_tokenize() {
         let text = "Hello World!".to_string();
         let tokens = tokenize(text, &crate::options::default());
-        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens.len(), 4);
         assert_eq!(tokens[0].1.text, "Hello");
         assert_eq!(tokens[1].1.text, "World");
         assert_eq!(tokens[2].1.text, "!");
         assert_eq!(tokens[0].0, TokenType::Id("Hello".to_string()));
         assert_eq!(tokens[1].0, TokenType::Id("World".to_string()));
         assert_eq!(tokens[2].0, TokenType::Exclamation);
+        assert_eq!(tokens[3].0, TokenType::EndOfInput);
+    }
+
+    #[test]
+    fn tokenize_interned_shares_symbols_between_inputs() {
+        let options = crate::options::default();
+        let mut interner = crate::interner::Interner::new();
+        let first = tokenize_interned("let x = y + x".to_string(), &options, &mut interner);
+        let second = tokenize_interned("x(\"x\")".to_string(), &options, &mut interner);
+
+        assert_eq!(first[1].0, first[5].0);
+        assert_eq!(first[1].0, second[0].0);
+        assert_ne!(first[1].0, first[3].0);
+        assert_eq!(second[2].0, TokenType::StringLiteral("x".to_string()));
+        match first[3].0 {
+            TokenType::Symbol(symbol) => assert_eq!(interner.resolve(symbol), "y"),
+            ref other => panic!("unexpected token {:?}", other)
+        }
+        assert_eq!(interner.len(), 3);
+
+        let shared = crate::interner::SharedInterner::from(interner);
+        assert_eq!(tokenize_interned("y".to_string(), &options, &shared)[0].0, first[3].0);
+    }
+
+    #[test]
+    fn tokenize_interned_keeps_no_identifier_strings() {
+        let options = crate::options::default();
+        let source = "alpha beta gamma delta\n".repeat(10_000);
+        let mut interner = crate::interner::Interner::new();
+        let plain = tokenize(source.clone(), &options);
+        let interned = tokenize_interned(source.clone(), &options, &mut interner);
+
+        // Identifiers no longer hold their name twice, once as text and once as value
+        let names: usize = plain.iter().filter(|token| matches!(token.0, TokenType::Id(_))).map(|token| token.1.text.len()).sum();
+        let saved = crate::token_buffer::tokens_heap_size(&plain) - crate::token_buffer::tokens_heap_size(&interned);
+        assert!(saved >= 2 * names, "{} bytes saved for {} bytes of names", saved, names);
+        assert!(interned.iter().all(|token| !matches!(token.0, TokenType::Symbol(_)) || token.1.text.capacity() == 0));
+
+        // Nor is any string allocated for them once their names were interned, only for the line breaks
+        let allocations = count_allocations(|| { tokenize_interned(source, &options, &mut interner); });
+        assert!(allocations < 10_000 + 100, "{} allocations for {} tokens", allocations, interned.len());
+    }
+
+    #[test]
+    fn tokenize_numeric_literals() {
+        let tokens = tokenize("f(0b1010, 1_000_000, 10u8, 99999999999999999999)".to_string(), &crate::options::default());
+        assert_eq!(tokens[2].0, TokenType::IntegerLiteral(10));
+        assert_eq!(tokens[4].0, TokenType::IntegerLiteral(1_000_000));
+        assert_eq!(tokens[6].0, TokenType::IntegerLiteral(10));
+        assert_eq!(tokens[6].1.number.as_ref().and_then(|number| number.suffix.as_deref()), Some("u8"));
+        assert_eq!(tokens[8].0, TokenType::Error(crate::error::TokenizeErrorKind::NumberOverflow));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn tokenize_exact_numbers() {
+        let mut options = crate::options::default();
+        options.exact_numbers = true;
+        let tokens = tokenize("id = 18446744073709551616; price = 0.1".to_string(), &options);
+        match (&tokens[2].0, &tokens[6].0) {
+            (TokenType::ExactIntegerLiteral(id), TokenType::ExactDecimalLiteral(price)) => {
+                assert_eq!(id.to_u128(), Some(18446744073709551616));
+                assert_eq!(price.to_decimal().unwrap().to_string(), "0.1");
+            },
+            other => panic!("unexpected tokens {:?}", other)
+        }
+    }
+
+    #[test]
+    fn try_tokenize_reports_invalid_escapes_with_spans() {
+        let err = try_tokenize(r#"say("ok \q", "\u{D800}")"#.to_string(), &crate::options::default()).unwrap_err();
+        assert_eq!(err.kind, crate::error::TokenizeErrorKind::InvalidEscape);
+        assert_eq!((err.span.start, err.span.end), (4, 11));
+
+        let tokens = tokenize(r#"say("\u{D800}")"#.to_string(), &crate::options::default());
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(tokens[2].0, TokenType::Error(crate::error::TokenizeErrorKind::LoneSurrogate));
+        assert_eq!(tokens[2].1.text, r#""\u{D800}""#);
+        assert_eq!((tokens[2].1.span.start, tokens[2].1.span.end), (4, 14));
+        assert_eq!(tokens[3].0, TokenType::CloseParenthesis);
+
+        let tokens = tokenize(r#"a = "\q""#.to_string(), &crate::options::default());
+        assert_eq!(crate::detokenizer::detokenize(&tokens, &Default::default()), r#"a = "\q""#);
+    }
+
+    #[test]
+    fn try_tokenize_reports_unterminated_literals() {
+        let err = try_tokenize("x = \"abc".to_string(), &crate::options::default()).unwrap_err();
+        assert_eq!(err.kind, crate::error::TokenizeErrorKind::UnterminatedLiteral);
+        assert_eq!((err.span.start, err.span.end), (4, 8));
+
+        let tokens = tokenize("f('a\\".to_string(), &crate::options::default());
+        assert_eq!(tokens[2].0, TokenType::Error(crate::error::TokenizeErrorKind::UnterminatedLiteral));
+        assert_eq!(tokens[2].1.text, "'a\\");
+        assert_eq!(tokens[3].0, TokenType::EndOfInput);
+    }
+
+    #[test]
+    fn tokenize_keeps_raw_and_cooked_literals() {
+        let options = crate::options::default();
+        let tokens = tokenize(r#"x = "a\tb" + 'c'"#.to_string(), &options);
+        assert_eq!(tokens[2].0, TokenType::StringLiteral("a\tb".to_string()));
+        assert_eq!(tokens[2].raw(), r#""a\tb""#);
+        assert_eq!(tokens[2].cooked().as_deref(), Some("a\tb"));
+        assert_eq!(tokens[2].1.scope, Some(options.scopes[0].id));
+        assert_eq!(tokens[4].raw(), "'c'");
+        assert_eq!(tokens[4].cooked().as_deref(), Some("c"));
+        assert_eq!(tokens[4].1.scope, Some(options.scopes[1].id));
+        assert_eq!(tokens[0].1.scope, None);
+        assert_eq!(tokens[0].cooked(), None);
+    }
+
+    #[test]
+    fn tokenize_text_matches_source() {
+        let source = r#"say("\u{41}\n", 'x', 0x1F) // "unterminated"#;
+        for token in tokenize(source.to_string(), &crate::options::default()) {
+            assert_eq!(token.1.text, source[token.1.span.start..token.1.span.end]);
+        }
+    }
+
+    #[test]
+    fn tokenize_counts_each_line_break_once() {
+        let tokens = tokenize("foo\r\nbar\rbaz\u{2028}\"a\r\nb\" qux".to_string(), &crate::options::default());
+        let kinds: Vec<TokenType> = tokens.iter().map(|token| token.0.clone()).collect();
+        assert_eq!(kinds, vec![
+            TokenType::Id("foo".to_string()), TokenType::Newline(LineEnding::CrLf),
+            TokenType::Id("bar".to_string()), TokenType::Newline(LineEnding::Cr),
+            TokenType::Id("baz".to_string()), TokenType::Newline(LineEnding::LineSeparator),
+            TokenType::StringLiteral("a\r\nb".to_string()), TokenType::Id("qux".to_string()),
+            TokenType::EndOfInput
+        ]);
+        assert_eq!(tokens[1].1.text, "\r\n");
+        let lines: Vec<i32> = tokens.iter().map(|token| token.1.position.line).collect();
+        assert_eq!(lines, vec![0, 0, 1, 1, 2, 2, 4, 4, 4]);
+    }
+
+    #[test]
+    fn tokenize_treats_rejected_line_breaks_as_blank() {
+        let mut options = crate::options::default();
+        options.line_endings = LineEndings::Lf;
+        let tokens = tokenize("foo\r\nbar\rbaz".to_string(), &options);
+        let texts: Vec<&str> = tokens.iter().map(|token| token.1.text.as_str()).collect();
+        assert_eq!(texts, vec!["foo", "\n", "bar", "baz", ""]);
+
+        options.line_endings = LineEndings::Auto;
+        let tokens = tokenize("foo\r\nbar\nbaz".to_string(), &options);
+        let texts: Vec<&str> = tokens.iter().map(|token| token.1.text.as_str()).collect();
+        assert_eq!(texts, vec!["foo", "\r\n", "bar", "baz", ""]);
+    }
+
+    #[test]
+    fn tokenize_ends_with_end_of_input() {
+        let source = "a\nbc d";
+        let tokens = tokenize(source.to_string(), &crate::options::default());
+        let end = tokens.last().unwrap();
+        assert_eq!(end.0, TokenType::EndOfInput);
+        assert_eq!((end.1.position.line, end.1.position.position), (1, 4));
+        assert_eq!((end.1.span.start, end.1.span.end), (source.len(), source.len()));
+    }
+
+    #[test]
+    fn tokenize_bytes_decodes_before_tokenizing() {
+        let tokens = tokenize_bytes(b"\xEF\xBB\xBFx = \"\xC3\xA9\"", &crate::options::default()).unwrap();
+        assert_eq!(tokens[0].0, TokenType::Id("x".to_string()));
+        assert_eq!(tokens[2].0, TokenType::StringLiteral("é".to_string()));
+
+        let tokens = tokenize_bytes_as(b"x = \"\x93hi\x94\"", Encoding::Windows1252, &crate::options::default()).unwrap();
+        assert_eq!(tokens[2].0, TokenType::StringLiteral("“hi”".to_string()));
+
+        let err = tokenize_bytes(b"x = \xC3(", &crate::options::default()).unwrap_err();
+        assert_eq!(err.offset, 4);
+    }
+
+    #[test]
+    fn legacy_options_keep_eof_tokens() {
+        let tokens = tokenize("a\r\nb".to_string(), &crate::options::legacy());
+        let kinds: Vec<TokenType> = tokens.into_iter().map(|token| token.0).collect();
+        assert_eq!(kinds, vec![TokenType::Id("a".to_string()), TokenType::EOF, TokenType::Id("b".to_string())]);
+    }
+}